@@ -0,0 +1,263 @@
+use std::fmt::Display;
+
+use crate::{
+    ast::{lexer, rpn, value::Value, AstEvaluator, AstStatement},
+    cli::Settings,
+    command::Command,
+};
+
+/// The start of the comment which holds a line's result
+const MARKER: &str = "# => ";
+
+/// A line of a worksheet, and the result of each statement on it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    /// The line without its results
+    pub code: String,
+    pub results: Vec<String>,
+}
+
+/// Lines are shown with their results as a comment: `1 + 2  # => 3`
+impl Display for Line {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.code)?;
+        if !self.results.is_empty() {
+            write!(f, "  {}{}", MARKER, self.results.join("; "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Evaluate every line of a worksheet with a fresh evaluator, ignoring any results which were
+/// added to it before.
+pub fn evaluate(input: &str, settings: Settings) -> Vec<Line> {
+    Session::new(settings).evaluate(input)
+}
+
+/// An evaluator along with the settings and stack which commands and earlier lines have changed,
+/// so that several pieces of a document can be evaluated as one worksheet
+pub struct Session {
+    eval: AstEvaluator,
+    stack: rpn::Stack,
+    settings: Settings,
+}
+
+impl Session {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            eval: AstEvaluator::new(),
+            stack: Default::default(),
+            settings,
+        }
+    }
+
+    /// Evaluate every line of `input`, ignoring any results which were added to it before.
+    ///
+    /// Lines with several statements have a result for each one, lines which end with a `\` or
+    /// inside a `/* block comment */` have their results on the last line of the expression, and
+    /// commands apply to the lines after them.
+    pub fn evaluate(&mut self, input: &str) -> Vec<Line> {
+        let mut out = Vec::new();
+        let mut expr = String::new();
+        for line in input.lines() {
+            let code = strip(line);
+            if let Some(code) = code.strip_suffix('\\') {
+                // keep the line break, so that a `# comment` ends with its line
+                expr.push_str(code);
+                expr.push('\n');
+                out.push(Line {
+                    code: line.into(),
+                    results: vec![],
+                });
+                continue;
+            }
+            expr.push_str(code);
+            if lexer::ends_in_comment(&expr) {
+                // a `/* block comment */` can span lines, so the lines are evaluated once it ends
+                expr.push('\n');
+                out.push(Line {
+                    code: code.into(),
+                    results: vec![],
+                });
+                continue;
+            }
+            let results = self.eval_line(&expr);
+            expr.clear();
+
+            out.push(Line {
+                code: code.into(),
+                results,
+            });
+        }
+        if lexer::ends_in_comment(&expr) {
+            // a comment which never ends is an error, shown on the last line
+            if let Some(last) = out.last_mut() {
+                last.results = self.eval_line(&expr);
+            }
+        }
+        out
+    }
+
+    /// Evaluate one line, giving the result of each statement on it
+    fn eval_line(&mut self, line: &str) -> Vec<String> {
+        let Self {
+            eval,
+            stack,
+            settings,
+        } = self;
+
+        if line.trim_start().starts_with(':') {
+            return match line.parse::<Command>().and_then(|c| c.run(settings)) {
+                Ok(()) => vec![],
+                Err(e) => vec![error(e)],
+            };
+        }
+
+        if settings.rpn {
+            return match stack.eval_line(eval, line, settings.signed_width()) {
+                Ok(()) => stack
+                    .values
+                    .last()
+                    .map(|top| {
+                        eval.variable_map.insert("_".into(), top.clone());
+                        format(settings, top)
+                    })
+                    .into_iter()
+                    .collect(),
+                Err(e) => vec![error(e)],
+            };
+        }
+
+        match AstStatement::parse_statements(line, settings.signed_width()) {
+            Ok(statements) => statements
+                .iter()
+                .map(|s| match eval.eval(s) {
+                    Ok(v) => {
+                        eval.variable_map.insert("_".into(), v.clone());
+                        format(settings, &v)
+                    }
+                    Err(e) => error(e),
+                })
+                .collect(),
+            Err(e) => vec![error(e)],
+        }
+    }
+}
+
+/// Add the result of each line of a worksheet to the end of the line as a comment.  Results
+/// which are already there are replaced, so a worksheet can be annotated again after it's
+/// edited.
+pub fn annotate(input: &str, settings: Settings) -> String {
+    let mut out = String::new();
+    for line in evaluate(input, settings) {
+        out.push_str(&line.to_string());
+        out.push('\n');
+    }
+    if !input.ends_with('\n') {
+        out.pop();
+    }
+    out
+}
+
+/// Remove the result which was added to a line, if there is one.  Only a `# => ` within a
+/// comment is a result, so one inside a string like `"# => "` is kept.
+fn strip(line: &str) -> &str {
+    // comment spans count characters, not bytes
+    let chars: Vec<_> = line.char_indices().map(|(i, _)| i).collect();
+    let byte = |i| chars.get(i).copied().unwrap_or(line.len());
+    let marker = lexer::comments(line)
+        .into_iter()
+        .filter(|c| c.line)
+        .find_map(|c| {
+            let start = byte(c.span.start());
+            line[start..byte(c.span.end())]
+                .find(MARKER)
+                .map(|i| start + i)
+        });
+    match marker {
+        Some(i) => line[..i].trim_end(),
+        None => line,
+    }
+}
+
+/// Show a result on one line, since it has to fit in a comment
+fn format(settings: &Settings, v: &Value) -> String {
+    match settings.output.format(v) {
+        Ok(out) => out.replace('\n', "; "),
+        Err(e) => error(e),
+    }
+}
+
+fn error(e: anyhow::Error) -> String {
+    format!("error: {}", e.root_cause())
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli::Cli;
+
+    fn settings(args: &[&str]) -> Settings {
+        Cli::parse_from([&["jacc"], args].concat())
+            .settings()
+            .unwrap()
+    }
+
+    #[test]
+    fn worksheet() {
+        let input = "# costs\n\
+                     1 + 2; _ * 2\n\
+                     2 ** \\\n\
+                     10  # => 5\n\
+                     \n\
+                     :format hex\n\
+                     255 // max\n\
+                     nope\n";
+        let expected = "# costs\n\
+                        1 + 2; _ * 2  # => 3; 6\n\
+                        2 ** \\\n\
+                        10  # => 1024\n\
+                        \n\
+                        :format hex\n\
+                        255 // max  # => 0xff\n\
+                        nope  # => error: Undeclared variable or constant: 'nope'\n";
+        let annotated = annotate(input, settings(&[]));
+        assert_eq!(annotated, expected);
+        // annotating it again changes nothing
+        assert_eq!(annotate(&annotated, settings(&[])), expected);
+    }
+
+    #[test]
+    fn marker_in_literal() {
+        let input = "len(\"é # => \")  # => old\nlen(\"a # => b\") # note # => 1";
+        assert_eq!(
+            annotate(input, settings(&[])),
+            "len(\"é # => \")  # => 8\nlen(\"a # => b\") # note  # => 8"
+        );
+    }
+
+    #[test]
+    fn block_comment() {
+        assert_eq!(
+            annotate(
+                "/* header\n explains things */\n1 + 2 /* one\n */ * 3",
+                settings(&[])
+            ),
+            "/* header\n explains things */\n1 + 2 /* one\n */ * 3  # => 7"
+        );
+        assert_eq!(
+            annotate("1\n/* one\ntwo", settings(&[])),
+            "1  # => 1\n/* one\ntwo  # => error: Unterminated block comment, expected '*/'"
+        );
+    }
+
+    #[test]
+    fn rpn() {
+        assert_eq!(
+            annotate("3 4 +\n2 *", settings(&["--rpn"])),
+            "3 4 +  # => 7\n2 *  # => 14"
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use anyhow::ensure;
+
+/// Check that `width` is a valid number of bits to operate on
+pub fn check_width(width: i128) -> anyhow::Result<u32> {
+    ensure!(
+        (1..=128).contains(&width),
+        "Width must be between 1 and 128 bits, found {}",
+        width
+    );
+    Ok(width as u32)
+}
+
+/// A mask of the lowest `width` bits: `mask(4) == 0b1111`
+pub fn mask(width: u32) -> u128 {
+    if width >= 128 {
+        u128::MAX
+    } else {
+        (1 << width) - 1
+    }
+}
+
+/// The smallest signed integer which fits in `width` bits
+pub fn min_signed(width: u32) -> i128 {
+    if width >= 128 {
+        i128::MIN
+    } else {
+        -(1 << (width - 1))
+    }
+}
+
+/// Get the lowest `width` bits of `x`, treating negative numbers as two's complement
+pub fn truncate(x: i128, width: u32) -> u128 {
+    x as u128 & mask(width)
+}
+
+/// Count the leading zeros of `x` within `width` bits
+pub fn clz(x: u128, width: u32) -> u32 {
+    x.leading_zeros() - (128 - width)
+}
+
+/// Count the trailing zeros of `x` within `width` bits
+pub fn ctz(x: u128, width: u32) -> u32 {
+    x.trailing_zeros().min(width)
+}
+
+/// Reverse the order of the lowest `width` bits of `x`
+pub fn bitrev(x: u128, width: u32) -> u128 {
+    x.reverse_bits() >> (128 - width)
+}
+
+/// Rotate the lowest `width` bits of `x` left by `n`
+pub fn rotl(x: u128, n: u32, width: u32) -> u128 {
+    let n = n % width;
+    if n == 0 {
+        return x;
+    }
+    ((x << n) | (x >> (width - n))) & mask(width)
+}
+
+/// Rotate the lowest `width` bits of `x` right by `n`
+pub fn rotr(x: u128, n: u32, width: u32) -> u128 {
+    rotl(x, width - n % width, width)
+}
+
+/// Sign-extend the lowest `width` bits of `x`
+pub fn sext(x: u128, width: u32) -> i128 {
+    let shift = 128 - width;
+    ((x << shift) as i128) >> shift
+}
+
+/// Extract the bits from `hi` down to `lo` (inclusive) of `x`
+pub fn field(x: u128, hi: u32, lo: u32) -> u128 {
+    (x >> lo) & mask(hi - lo + 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn bits() {
+        assert_eq!(min_signed(8), -128);
+        assert_eq!(min_signed(128), i128::MIN);
+        assert_eq!(truncate(-1, 8), 0xff);
+        assert_eq!(clz(1, 32), 31);
+        assert_eq!(ctz(0, 16), 16);
+        assert_eq!(bitrev(0b0001, 4), 0b1000);
+        assert_eq!(rotl(0x81, 1, 8), 0x03);
+        assert_eq!(rotr(0x81, 1, 8), 0xc0);
+        assert_eq!(rotl(1 << 127, 1, 128), 1);
+        assert_eq!(sext(0xff, 8), -1);
+        assert_eq!(sext(0x7f, 8), 127);
+        assert_eq!(field(0xabcd, 11, 4), 0xbc);
+    }
+}
@@ -0,0 +1,310 @@
+use anyhow::{bail, ensure};
+
+/// The tolerance used when integrating and finding roots
+const TOLERANCE: f64 = 1e-12;
+/// The maximum number of iterations when finding roots
+const MAX_ITERATIONS: usize = 200;
+
+/// Find the derivative of `f` at `x` using Ridders' method: central differences with decreasing
+/// step sizes, extrapolated to a step size of zero
+pub fn derivative<F>(f: F, x: f64) -> anyhow::Result<f64>
+where
+    F: Fn(f64) -> anyhow::Result<f64>,
+{
+    const SHRINK: f64 = 1.4;
+    const STEPS: usize = 10;
+
+    let central = |h: f64| Ok::<_, anyhow::Error>((f(x + h)? - f(x - h)?) / (2.0 * h));
+
+    let mut h = 0.1 * x.abs().max(1.0);
+    // table[j][i] is the estimate with step i, extrapolated j times
+    let mut table = [[0.0; STEPS]; STEPS];
+    table[0][0] = central(h)?;
+    let mut best = table[0][0];
+    let mut err = f64::INFINITY;
+
+    for i in 1..STEPS {
+        h /= SHRINK;
+        table[0][i] = central(h)?;
+        let mut fac = SHRINK * SHRINK;
+        for j in 1..=i {
+            table[j][i] = (table[j - 1][i] * fac - table[j - 1][i - 1]) / (fac - 1.0);
+            fac *= SHRINK * SHRINK;
+            let e = (table[j][i] - table[j - 1][i])
+                .abs()
+                .max((table[j][i] - table[j - 1][i - 1]).abs());
+            if e <= err {
+                err = e;
+                best = table[j][i];
+            }
+        }
+        // Stop once the higher orders start getting worse, which is due to rounding
+        if (table[i][i] - table[i - 1][i - 1]).abs() >= 2.0 * err {
+            break;
+        }
+    }
+
+    Ok(best)
+}
+
+/// Find the integral of `f` from `a` to `b` using adaptive Simpson's rule.  Either bound may be
+/// infinite, in which case the interval is mapped onto a finite one.
+pub fn integrate<F>(f: F, a: f64, b: f64) -> anyhow::Result<f64>
+where
+    F: Fn(f64) -> anyhow::Result<f64>,
+{
+    ensure!(!a.is_nan() && !b.is_nan(), "Bounds must be numbers");
+    if a == b {
+        return Ok(0.0);
+    }
+    if a > b {
+        return Ok(-integrate(f, b, a)?);
+    }
+
+    // The integrand must vanish at infinity, so the transformed integrand is 0 at the ends of its
+    // interval where it would otherwise be undefined
+    let out = match (a.is_finite(), b.is_finite()) {
+        (true, true) => simpson(&f, a, b)?,
+        (true, false) => simpson(
+            &|t: f64| {
+                if t == 1.0 {
+                    return Ok(0.0);
+                }
+                Ok(f(a + t / (1.0 - t))? / (1.0 - t).powi(2))
+            },
+            0.0,
+            1.0,
+        )?,
+        (false, true) => simpson(
+            &|t: f64| {
+                if t == 0.0 {
+                    return Ok(0.0);
+                }
+                Ok(f(b - (1.0 - t) / t)? / (t * t))
+            },
+            0.0,
+            1.0,
+        )?,
+        (false, false) => simpson(
+            &|t: f64| {
+                if t.abs() == 1.0 {
+                    return Ok(0.0);
+                }
+                let d = 1.0 - t * t;
+                Ok(f(t / d)? * (1.0 + t * t) / (d * d))
+            },
+            -1.0,
+            1.0,
+        )?,
+    };
+
+    ensure!(out.is_finite(), "The integral does not converge");
+    Ok(out)
+}
+
+/// Adaptive Simpson's rule over a finite interval
+fn simpson(f: &dyn Fn(f64) -> anyhow::Result<f64>, a: f64, b: f64) -> anyhow::Result<f64> {
+    /// One step, recursing into each half of the interval until the estimate is accurate
+    #[allow(clippy::too_many_arguments)]
+    fn step(
+        f: &dyn Fn(f64) -> anyhow::Result<f64>,
+        a: f64,
+        b: f64,
+        fa: f64,
+        fm: f64,
+        fb: f64,
+        whole: f64,
+        tolerance: f64,
+        depth: u32,
+    ) -> anyhow::Result<f64> {
+        let m = (a + b) / 2.0;
+        let (lm, rm) = ((a + m) / 2.0, (m + b) / 2.0);
+        let (flm, frm) = (f(lm)?, f(rm)?);
+        let left = (m - a) / 6.0 * (fa + 4.0 * flm + fm);
+        let right = (b - m) / 6.0 * (fm + 4.0 * frm + fb);
+        let delta = left + right - whole;
+
+        if depth == 0 || delta.abs() <= 15.0 * tolerance {
+            // Richardson extrapolation of the two estimates
+            return Ok(left + right + delta / 15.0);
+        }
+        Ok(
+            step(f, a, m, fa, flm, fm, left, tolerance / 2.0, depth - 1)?
+                + step(f, m, b, fm, frm, fb, right, tolerance / 2.0, depth - 1)?,
+        )
+    }
+
+    let m = (a + b) / 2.0;
+    let (fa, fm, fb) = (f(a)?, f(m)?, f(b)?);
+    let whole = (b - a) / 6.0 * (fa + 4.0 * fm + fb);
+    step(f, a, b, fa, fm, fb, whole, TOLERANCE, 40)
+}
+
+/// Find a root of `f` near `guess` using Newton's method
+pub fn newton<F>(f: F, guess: f64) -> anyhow::Result<f64>
+where
+    F: Fn(f64) -> anyhow::Result<f64>,
+{
+    let mut x = guess;
+    for _ in 0..MAX_ITERATIONS {
+        let y = f(x)?;
+        if y == 0.0 {
+            return Ok(x);
+        }
+
+        let slope = derivative(&f, x)?;
+        ensure!(
+            slope != 0.0 && slope.is_finite(),
+            "The slope at {} is {}, try another guess",
+            x,
+            slope
+        );
+
+        let step = y / slope;
+        x -= step;
+        ensure!(x.is_finite(), "Diverged, try another guess");
+        if step.abs() <= TOLERANCE * x.abs().max(1.0) {
+            return Ok(x);
+        }
+    }
+    bail!("Did not converge, try another guess")
+}
+
+/// Find a root of `f` between `a` and `b` using Brent's method.  `f(a)` and `f(b)` must have
+/// opposite signs.
+pub fn brent<F>(f: F, a: f64, b: f64) -> anyhow::Result<f64>
+where
+    F: Fn(f64) -> anyhow::Result<f64>,
+{
+    let (mut a, mut b) = (a, b);
+    let (mut fa, mut fb) = (f(a)?, f(b)?);
+    if fa == 0.0 {
+        return Ok(a);
+    }
+    if fb == 0.0 {
+        return Ok(b);
+    }
+    ensure!(
+        fa.signum() != fb.signum(),
+        "f({}) and f({}) must have opposite signs, found {} and {}",
+        a,
+        b,
+        fa,
+        fb
+    );
+
+    // `b` is the best estimate, `a` is the previous one, and `c` is on the other side of the root
+    let (mut c, mut fc) = (a, fa);
+    let mut d = b - a;
+    let mut e = d;
+    for _ in 0..MAX_ITERATIONS {
+        if fb.signum() == fc.signum() {
+            (c, fc) = (a, fa);
+            d = b - a;
+            e = d;
+        }
+        if fc.abs() < fb.abs() {
+            (a, fa) = (b, fb);
+            (b, fb) = (c, fc);
+            (c, fc) = (a, fa);
+        }
+
+        let tolerance = 2.0 * f64::EPSILON * b.abs() + TOLERANCE / 2.0;
+        let m = (c - b) / 2.0;
+        if m.abs() <= tolerance || fb == 0.0 {
+            return Ok(b);
+        }
+
+        if e.abs() >= tolerance && fa.abs() > fb.abs() {
+            // Try inverse quadratic interpolation, or the secant method if `a == c`
+            let s = fb / fa;
+            let (mut p, mut q) = if a == c {
+                (2.0 * m * s, 1.0 - s)
+            } else {
+                let q = fa / fc;
+                let r = fb / fc;
+                (
+                    s * (2.0 * m * q * (q - r) - (b - a) * (r - 1.0)),
+                    (q - 1.0) * (r - 1.0) * (s - 1.0),
+                )
+            };
+            if p > 0.0 {
+                q = -q;
+            } else {
+                p = -p;
+            }
+
+            if 2.0 * p < (3.0 * m * q - (tolerance * q).abs()).min((e * q).abs()) {
+                e = d;
+                d = p / q;
+            } else {
+                // The interpolation is not good enough, so bisect instead
+                d = m;
+                e = m;
+            }
+        } else {
+            d = m;
+            e = m;
+        }
+
+        (a, fa) = (b, fb);
+        b += if d.abs() > tolerance {
+            d
+        } else {
+            tolerance.copysign(m)
+        };
+        fb = f(b)?;
+    }
+    bail!("Did not converge")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-8 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn derivatives() {
+        assert!(close(derivative(|x| Ok(x.sin()), 0.0).unwrap(), 1.0));
+        assert!(close(derivative(|x| Ok(x.powi(3)), 2.0).unwrap(), 12.0));
+        assert!(close(
+            derivative(|x| Ok(x.exp()), 10.0).unwrap(),
+            10f64.exp()
+        ));
+    }
+
+    #[test]
+    fn integrals() {
+        assert!(close(integrate(|x| Ok(x * x), 0.0, 3.0).unwrap(), 9.0));
+        assert!(close(
+            integrate(|x| Ok(x.sin()), 0.0, std::f64::consts::PI).unwrap(),
+            2.0
+        ));
+        assert!(close(integrate(|x| Ok(x * x), 3.0, 0.0).unwrap(), -9.0));
+        assert!(close(
+            integrate(|x| Ok((-x * x).exp()), f64::NEG_INFINITY, f64::INFINITY).unwrap(),
+            std::f64::consts::PI.sqrt()
+        ));
+        assert!(close(
+            integrate(|x| Ok(1.0 / (x * x)), 1.0, f64::INFINITY).unwrap(),
+            1.0
+        ));
+    }
+
+    #[test]
+    fn roots() {
+        assert!(close(
+            newton(|x| Ok(x * x - 2.0), 1.0).unwrap(),
+            std::f64::consts::SQRT_2
+        ));
+        assert!(close(
+            brent(|x| Ok(x.cos() - x), 0.0, 1.0).unwrap(),
+            0.7390851332151607
+        ));
+        assert!(brent(|x| Ok(x * x + 1.0), -1.0, 1.0).is_err());
+        assert!(newton(|x| Ok(x * x + 1.0), 0.0).is_err());
+    }
+}
@@ -0,0 +1,193 @@
+/// The parameters of a CRC, using the names from the catalogue of parametrised CRC algorithms
+pub struct Crc {
+    /// The number of bits in the CRC, at least 8
+    pub width: u32,
+    pub poly: u64,
+    pub init: u64,
+    /// Whether each input byte is reflected (least significant bit first)
+    pub refin: bool,
+    /// Whether the final CRC is reflected
+    pub refout: bool,
+    pub xorout: u64,
+}
+
+/// CRC-8/SMBUS
+pub const CRC8: Crc = Crc {
+    width: 8,
+    poly: 0x07,
+    init: 0,
+    refin: false,
+    refout: false,
+    xorout: 0,
+};
+
+/// CRC-8/MAXIM-DOW, used by 1-Wire devices
+pub const CRC8_MAXIM: Crc = Crc {
+    width: 8,
+    poly: 0x31,
+    init: 0,
+    refin: true,
+    refout: true,
+    xorout: 0,
+};
+
+/// CRC-16/ARC
+pub const CRC16: Crc = Crc {
+    width: 16,
+    poly: 0x8005,
+    init: 0,
+    refin: true,
+    refout: true,
+    xorout: 0,
+};
+
+/// CRC-16/IBM-3740, usually called CRC-16/CCITT-FALSE
+pub const CRC16_CCITT: Crc = Crc {
+    width: 16,
+    poly: 0x1021,
+    init: 0xffff,
+    refin: false,
+    refout: false,
+    xorout: 0,
+};
+
+/// CRC-16/XMODEM
+pub const CRC16_XMODEM: Crc = Crc {
+    width: 16,
+    poly: 0x1021,
+    init: 0,
+    refin: false,
+    refout: false,
+    xorout: 0,
+};
+
+/// CRC-16/KERMIT
+pub const CRC16_KERMIT: Crc = Crc {
+    width: 16,
+    poly: 0x1021,
+    init: 0,
+    refin: true,
+    refout: true,
+    xorout: 0,
+};
+
+/// CRC-16/MODBUS
+pub const CRC16_MODBUS: Crc = Crc {
+    width: 16,
+    poly: 0x8005,
+    init: 0xffff,
+    refin: true,
+    refout: true,
+    xorout: 0,
+};
+
+/// CRC-32/ISO-HDLC, used by zip, ethernet, and png
+pub const CRC32: Crc = Crc {
+    width: 32,
+    poly: 0x04c11db7,
+    init: 0xffffffff,
+    refin: true,
+    refout: true,
+    xorout: 0xffffffff,
+};
+
+/// CRC-32/ISCSI, usually called CRC-32C
+pub const CRC32C: Crc = Crc {
+    width: 32,
+    poly: 0x1edc6f41,
+    init: 0xffffffff,
+    refin: true,
+    refout: true,
+    xorout: 0xffffffff,
+};
+
+impl Crc {
+    pub fn checksum(&self, data: &[u8]) -> u64 {
+        let mask = u64::MAX >> (64 - self.width);
+        let top = 1 << (self.width - 1);
+
+        let mut crc = self.init;
+        for &b in data {
+            let b = if self.refin { b.reverse_bits() } else { b };
+            crc ^= (b as u64) << (self.width - 8);
+            for _ in 0..8 {
+                crc = if crc & top != 0 {
+                    (crc << 1) ^ self.poly
+                } else {
+                    crc << 1
+                } & mask;
+            }
+        }
+
+        if self.refout {
+            crc = crc.reverse_bits() >> (64 - self.width);
+        }
+        crc ^ self.xorout
+    }
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD: u32 = 65521;
+    let (a, b) = data.iter().fold((1, 0), |(a, b), &d| {
+        let a = (a + d as u32) % MOD;
+        (a, (b + a) % MOD)
+    });
+    b << 16 | a
+}
+
+/// The 32-bit FNV-1a hash
+pub fn fnv1a32(data: &[u8]) -> u32 {
+    data.iter()
+        .fold(0x811c9dc5, |h, &d| (h ^ d as u32).wrapping_mul(0x01000193))
+}
+
+/// The 64-bit FNV-1a hash
+pub fn fnv1a64(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |h, &d| {
+        (h ^ d as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The sum of every byte, modulo `2^width`
+pub fn sum(data: &[u8], width: u32) -> u64 {
+    let sum = data.iter().fold(0u64, |s, &d| s.wrapping_add(d as u64));
+    sum & (u64::MAX >> (64 - width))
+}
+
+/// Every byte xor-ed together
+pub fn xor(data: &[u8]) -> u8 {
+    data.iter().fold(0, |x, &d| x ^ d)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The standard input used to check CRCs
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn crc() {
+        assert_eq!(CRC8.checksum(CHECK), 0xf4);
+        assert_eq!(CRC8_MAXIM.checksum(CHECK), 0xa1);
+        assert_eq!(CRC16.checksum(CHECK), 0xbb3d);
+        assert_eq!(CRC16_CCITT.checksum(CHECK), 0x29b1);
+        assert_eq!(CRC16_XMODEM.checksum(CHECK), 0x31c3);
+        assert_eq!(CRC16_KERMIT.checksum(CHECK), 0x2189);
+        assert_eq!(CRC16_MODBUS.checksum(CHECK), 0x4b37);
+        assert_eq!(CRC32.checksum(CHECK), 0xcbf43926);
+        assert_eq!(CRC32C.checksum(CHECK), 0xe3069283);
+        assert_eq!(CRC32.checksum(b""), 0);
+    }
+
+    #[test]
+    fn hashes() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(fnv1a32(b""), 0x811c9dc5);
+        assert_eq!(fnv1a32(b"a"), 0xe40c292c);
+        assert_eq!(fnv1a64(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(sum(&[0xff, 0x02], 8), 0x01);
+        assert_eq!(sum(&[0xff, 0x02], 16), 0x101);
+        assert_eq!(xor(&[0x0f, 0xff]), 0xf0);
+    }
+}
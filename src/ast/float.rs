@@ -0,0 +1,127 @@
+/// The number of bits in the mantissa of an f64, not including the implicit leading bit
+const MANTISSA_BITS: u32 = f64::MANTISSA_DIGITS - 1;
+/// The exponent bias of an f64
+const EXPONENT_BIAS: i32 = 1023;
+
+/// Find the next representable f64 after `x` in the direction of `y`
+pub fn next_after(x: f64, y: f64) -> f64 {
+    if x.is_nan() || y.is_nan() {
+        return f64::NAN;
+    }
+    if x == y {
+        return y;
+    }
+    if x == 0.0 {
+        // the smallest subnormal number, with the sign of the direction we're moving
+        return f64::from_bits(1).copysign(y - x);
+    }
+
+    // The bits of an f64 are ordered by magnitude, so moving away from zero is the next pattern
+    let bits = x.to_bits();
+    if (y > x) == (x > 0.0) {
+        f64::from_bits(bits + 1)
+    } else {
+        f64::from_bits(bits - 1)
+    }
+}
+
+/// The unit in the last place of `x`: the gap between `|x|` and the next f64 away from zero
+pub fn ulp(x: f64) -> f64 {
+    let x = x.abs();
+    if x == f64::MAX {
+        // there is no next f64, so use the gap below instead, which is the same size
+        x - next_after(x, 0.0)
+    } else {
+        next_after(x, f64::INFINITY) - x
+    }
+}
+
+/// The number of representable f64s between `a` and `b`
+///
+/// Note: neither `a` nor `b` may be NaN
+pub fn ulp_diff(a: f64, b: f64) -> u64 {
+    // Map the bits onto a line which is ordered the same as the numbers
+    fn key(x: f64) -> i64 {
+        let bits = x.to_bits() as i64;
+        if bits < 0 {
+            i64::MIN - bits
+        } else {
+            bits
+        }
+    }
+    key(a).abs_diff(key(b))
+}
+
+/// Show the bits of `x`, split into its sign, exponent, and mantissa:
+///
+/// ```text
+/// 0x3ff8000000000000 = 1.5
+/// sign     = 0 (+)
+/// exponent = 0x3ff (2^0)
+/// mantissa = 0x8000000000000 (1.5)
+/// ```
+pub fn render(x: f64) -> String {
+    let bits = x.to_bits();
+    let sign = bits >> 63;
+    let exponent = ((bits >> MANTISSA_BITS) & 0x7ff) as i32;
+    let mantissa = bits & ((1 << MANTISSA_BITS) - 1);
+
+    let fraction = mantissa as f64 / (1u64 << MANTISSA_BITS) as f64;
+    let (exponent_desc, significand) = match exponent {
+        0 => (format!("subnormal, 2^{}", 1 - EXPONENT_BIAS), fraction),
+        0x7ff if mantissa == 0 => ("infinity".into(), fraction),
+        0x7ff => ("NaN".into(), fraction),
+        e => (format!("2^{}", e - EXPONENT_BIAS), 1.0 + fraction),
+    };
+
+    format!(
+        "{:#018x} = {}\n\
+         sign     = {} ({})\n\
+         exponent = {:#x} ({})\n\
+         mantissa = {:#x} ({})",
+        bits,
+        x,
+        sign,
+        if sign == 1 { '-' } else { '+' },
+        exponent,
+        exponent_desc,
+        mantissa,
+        significand
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn neighbours() {
+        assert_eq!(next_after(1.0, 2.0), 1.0 + f64::EPSILON);
+        assert_eq!(next_after(1.0, 0.0), 1.0 - f64::EPSILON / 2.0);
+        assert_eq!(next_after(-1.0, -2.0), -1.0 - f64::EPSILON);
+        assert_eq!(next_after(0.0, -1.0), -f64::from_bits(1));
+        assert_eq!(ulp(1.0), f64::EPSILON);
+        assert_eq!(ulp(-1.0), f64::EPSILON);
+        assert_eq!(ulp(0.0), f64::from_bits(1));
+        assert_eq!(ulp_diff(1.0, 1.0 + 2.0 * f64::EPSILON), 2);
+        assert_eq!(ulp_diff(-f64::from_bits(1), f64::from_bits(1)), 2);
+    }
+
+    #[test]
+    fn decompose() {
+        assert_eq!(
+            render(1.5),
+            "0x3ff8000000000000 = 1.5\n\
+             sign     = 0 (+)\n\
+             exponent = 0x3ff (2^0)\n\
+             mantissa = 0x8000000000000 (1.5)"
+        );
+        assert_eq!(
+            render(-f64::INFINITY),
+            "0xfff0000000000000 = -inf\n\
+             sign     = 1 (-)\n\
+             exponent = 0x7ff (infinity)\n\
+             mantissa = 0x0 (0)"
+        );
+    }
+}
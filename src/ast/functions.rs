@@ -1,38 +1,107 @@
-use anyhow::ensure;
+use anyhow::{bail, ensure, Context};
 use std::collections::HashMap;
 
-use super::{AstEvaluator, AstStatement};
+use super::{
+    bits, calculus, checksum, float, integer,
+    matrix::{self, Matrix},
+    op::Operator,
+    polynomial, stats,
+    symbolic::Expr,
+    value::Value,
+    AstEvaluator, AstStatement,
+};
 
-pub type FnMap =
-    HashMap<String, Box<dyn Fn(&AstEvaluator, Vec<AstStatement>) -> anyhow::Result<f64>>>;
+/// A function which can be called by name, like `sin(x)`
+pub struct Function {
+    /// The number of arguments the function takes, which is also the number of values it takes
+    /// from the stack in RPN mode.  Functions which take a varying number of arguments give the
+    /// number they're most often called with.
+    pub arity: usize,
+    pub call: Box<FnBody>,
+}
+
+pub type FnBody = dyn Fn(&AstEvaluator, Vec<AstStatement>) -> anyhow::Result<Value>;
+
+pub type FnMap = HashMap<String, Function>;
 
 // This function signature is awful
 pub fn default_functions() -> FnMap {
     let mut map: FnMap = HashMap::new();
 
+    // `name(a, b) = ...` checks that the function is called with exactly those arguments, while
+    // `name / 2 = ...` and `name = ...` (for 1) leave it to the body to check
     macro_rules! def_fn {
+        (@insert $name: ident, $arity: expr, |$expr: ident, $args: ident| $body: block) => {
+            map.insert(
+                stringify!($name).into(),
+                Function {
+                    arity: $arity,
+                    call: Box::new(|$expr, $args| $body),
+                },
+            );
+        };
+        ($name: ident / $arity: literal = |$expr: ident, $args: ident| $body: block) => {
+            def_fn!(@insert $name, $arity, |$expr, $args| $body)
+        };
         ($name: ident = |$expr: ident, $args: ident| $body: block) => {
-            map.insert(stringify!($name).into(), Box::new(|$expr, $args| $body));
+            def_fn!(@insert $name, 1, |$expr, $args| $body)
+        };
+        ($name: ident($($param: ident),+) = |$expr: ident, $args: ident| $body: block) => {
+            def_fn!(@insert $name, [$(stringify!($param)),+].len(), |$expr, $args| {
+                let params = [$(stringify!($param)),+];
+                ensure!(
+                    $args.len() == params.len(),
+                    "Expected {} arg{}: `{}({})`",
+                    params.len(),
+                    if params.len() == 1 { "" } else { "s" },
+                    stringify!($name),
+                    params.join(", ")
+                );
+                $body
+            })
         };
         ($name: ident => $f: ident) => {
+            def_fn!($name(x) = |expr, args| { expr.eval(&args[0])?.map_number(f64::$f) })
+        };
+        ($name: ident) => {
+            def_fn!($name => $name)
+        };
+    }
+
+    // Define a function over a list (or many values) which contains at least `$min` numbers
+    macro_rules! def_stat {
+        ($name: ident, $min: literal => $f: expr) => {
             def_fn!(
                 $name = |expr, args| {
+                    let nums = numbers(expr, &args)?;
                     ensure!(
-                        args.len() == 1,
-                        concat!("Expected 1 arg: `", stringify!($name), "(x)`")
+                        nums.len() >= $min,
+                        concat!(
+                            "Expected at least ",
+                            $min,
+                            " number(s): `",
+                            stringify!($name),
+                            "(a, b, ...)`"
+                        )
                     );
-                    Ok(expr.eval(&args[0])?.$f())
+                    Ok(Value::from($f(&nums)))
                 }
             )
         };
-        ($name: ident) => {
-            def_fn!($name => $name)
+        ($name: ident => $f: expr) => {
+            def_stat!($name, 1 => $f)
         };
     }
 
     // logaritms
     def_fn!(ln);
     def_fn!(log => log10);
+    def_fn!(
+        log_B(x, base) = |expr, args| {
+            let base = expr.eval(&args[1])?.as_number()?;
+            expr.eval(&args[0])?.map_number(|n| n.log(base))
+        }
+    );
 
     // trig
     def_fn!(sin);
@@ -59,28 +128,953 @@ pub fn default_functions() -> FnMap {
     def_fn!(round);
     def_fn!(abs);
 
+    // number theory
+    def_fn!(
+        gcd / 2 = |expr, args| {
+            let nums = integers(expr, &args)?;
+            ensure!(
+                !nums.is_empty(),
+                "Expected at least 1 integer: `gcd(a, b, ...)`"
+            );
+            let gcd = nums
+                .into_iter()
+                .try_fold(0, |acc, n| i128::try_from(integer::gcd(acc, n)).ok())
+                .context("Result is too large")?;
+            Ok(Value::int(gcd))
+        }
+    );
+    def_fn!(
+        lcm / 2 = |expr, args| {
+            let nums = integers(expr, &args)?;
+            ensure!(
+                !nums.is_empty(),
+                "Expected at least 1 integer: `lcm(a, b, ...)`"
+            );
+            let lcm = nums
+                .into_iter()
+                .try_fold(1, integer::lcm)
+                .context("Result is too large")?;
+            Ok(Value::int(lcm))
+        }
+    );
+    def_fn!(
+        factorial(n) = |expr, args| {
+            let n = natural(expr, &args[0])?;
+            Ok(integer::factorial(n).map_or_else(
+                // Too large to be exact, so approximate it instead
+                || product((1..=n).map(|i| i as f64)).into(),
+                Value::int,
+            ))
+        }
+    );
+    def_fn!(
+        nCr(n, r) = |expr, args| {
+            let (n, r) = (natural(expr, &args[0])?, natural(expr, &args[1])?);
+            Ok(integer::choose(n, r).map_or_else(
+                || {
+                    let r = r.min(n - r);
+                    product((1..=r).map(|i| (n - r + i) as f64 / i as f64)).into()
+                },
+                Value::int,
+            ))
+        }
+    );
+    def_fn!(
+        nPr(n, r) = |expr, args| {
+            let (n, r) = (natural(expr, &args[0])?, natural(expr, &args[1])?);
+            Ok(integer::permute(n, r).map_or_else(
+                || product((n - r + 1..=n).map(|i| i as f64)).into(),
+                Value::int,
+            ))
+        }
+    );
+    def_fn!(
+        fib(n) = |expr, args| {
+            let n = natural(expr, &args[0])?;
+            Ok(integer::fib(n).map_or_else(
+                // Binet's formula
+                || {
+                    let phi = (1.0 + 5f64.sqrt()) / 2.0;
+                    (phi.powf(n as f64) / 5f64.sqrt()).round().into()
+                },
+                Value::int,
+            ))
+        }
+    );
+    def_fn!(
+        isprime(n) = |expr, args| {
+            let n = expr.eval(&args[0])?.as_integer()?;
+            Ok(Value::from(
+                (n >= 0 && integer::is_prime(n as u128)) as u8 as f64,
+            ))
+        }
+    );
+    def_fn!(
+        nextprime(n) = |expr, args| {
+            let n = expr.eval(&args[0])?.as_integer()?.max(1);
+            let p = integer::next_prime(n as u128);
+            Ok(Value::int(p.try_into().context("Result is too large")?))
+        }
+    );
+    def_fn!(
+        factor(n) = |expr, args| {
+            let n = natural(expr, &args[0])?;
+            ensure!(n > 0, "Cannot factor 0");
+            Ok(Value::List(
+                integer::factor(n)
+                    .into_iter()
+                    .map(|p| Value::int(p as i128))
+                    .collect(),
+            ))
+        }
+    );
+    def_fn!(
+        totient(n) = |expr, args| {
+            let n = natural(expr, &args[0])?;
+            ensure!(n > 0, "Expected a positive integer, found 0");
+            Ok(Value::int(integer::totient(n) as i128))
+        }
+    );
+    def_fn!(
+        modpow(b, e, m) = |expr, args| {
+            let [b, e, m] = [0, 1, 2].map(|i| expr.eval(&args[i])?.as_integer());
+            let (b, e, m) = (b?, e?, m?);
+            ensure!(m > 0, "Modulus must be positive, found {}", m);
+            let b = if e < 0 {
+                integer::mod_inv(b, m).with_context(|| format!("{} has no inverse mod {}", b, m))?
+            } else {
+                b.rem_euclid(m)
+            };
+            let p = integer::pow_mod(b as u128, e.unsigned_abs(), m as u128);
+            Ok(Value::int(p as i128))
+        }
+    );
+    def_fn!(
+        modinv(a, m) = |expr, args| {
+            let a = expr.eval(&args[0])?.as_integer()?;
+            let m = expr.eval(&args[1])?.as_integer()?;
+            ensure!(m > 0, "Modulus must be positive, found {}", m);
+            let inv = integer::mod_inv(a, m)
+                .with_context(|| format!("{} has no inverse mod {}", a, m))?;
+            Ok(Value::int(inv))
+        }
+    );
+    def_fn!(
+        div(a, b) = |expr, args| {
+            let a = expr.eval(&args[0])?.as_integer()?;
+            let b = expr.eval(&args[1])?.as_integer()?;
+            ensure!(b != 0, "Division by zero");
+            Ok(Value::int(
+                integer::floor_div(a, b).context("Result overflows")?,
+            ))
+        }
+    );
+    def_fn!(
+        mod(a, b) = |expr, args| {
+            let a = expr.eval(&args[0])?.as_integer()?;
+            let b = expr.eval(&args[1])?.as_integer()?;
+            ensure!(b != 0, "Division by zero");
+            Ok(Value::int(
+                integer::floor_mod(a, b).context("Result overflows")?,
+            ))
+        }
+    );
+
+    // bit manipulation
+    def_fn!(
+        popcount = |expr, args| {
+            ensure!(
+                (1..=2).contains(&args.len()),
+                "Expected 1 or 2 args: `popcount(x, width = 64)`"
+            );
+            let (x, _) = sized(expr, &args, 1)?;
+            Ok((x.count_ones() as f64).into())
+        }
+    );
+    def_fn!(
+        clz = |expr, args| {
+            ensure!(
+                (1..=2).contains(&args.len()),
+                "Expected 1 or 2 args: `clz(x, width = 64)`"
+            );
+            let (x, width) = sized(expr, &args, 1)?;
+            Ok((bits::clz(x, width) as f64).into())
+        }
+    );
+    def_fn!(
+        ctz = |expr, args| {
+            ensure!(
+                (1..=2).contains(&args.len()),
+                "Expected 1 or 2 args: `ctz(x, width = 64)`"
+            );
+            let (x, width) = sized(expr, &args, 1)?;
+            Ok((bits::ctz(x, width) as f64).into())
+        }
+    );
+    def_fn!(
+        bitrev(x, width) = |expr, args| {
+            let (x, width) = sized(expr, &args, 1)?;
+            from_bits(bits::bitrev(x, width))
+        }
+    );
+    def_fn!(
+        bswap16(x) = |expr, args| {
+            let x = fit(expr.eval(&args[0])?.as_integer()?, 16)?;
+            from_bits((x as u16).swap_bytes() as u128)
+        }
+    );
+    def_fn!(
+        bswap32(x) = |expr, args| {
+            let x = fit(expr.eval(&args[0])?.as_integer()?, 32)?;
+            from_bits((x as u32).swap_bytes() as u128)
+        }
+    );
+    def_fn!(
+        bswap64(x) = |expr, args| {
+            let x = fit(expr.eval(&args[0])?.as_integer()?, 64)?;
+            from_bits((x as u64).swap_bytes() as u128)
+        }
+    );
+    def_fn!(
+        bit(x, n) = |expr, args| {
+            let x = expr.eval(&args[0])?.as_integer()?;
+            let n = bit_index(expr, &args[1])?;
+            Ok((((x >> n) & 1) as f64).into())
+        }
+    );
+    def_fn!(
+        setbit(x, n) = |expr, args| {
+            let x = expr.eval(&args[0])?.as_integer()?;
+            let n = bit_index(expr, &args[1])?;
+            Ok(Value::int(x | 1 << n))
+        }
+    );
+    def_fn!(
+        clearbit(x, n) = |expr, args| {
+            let x = expr.eval(&args[0])?.as_integer()?;
+            let n = bit_index(expr, &args[1])?;
+            Ok(Value::int(x & !(1 << n)))
+        }
+    );
+    def_fn!(
+        togglebit(x, n) = |expr, args| {
+            let x = expr.eval(&args[0])?.as_integer()?;
+            let n = bit_index(expr, &args[1])?;
+            Ok(Value::int(x ^ 1 << n))
+        }
+    );
+    def_fn!(
+        bits(x, hi, lo) = |expr, args| {
+            let x = expr.eval(&args[0])?.as_integer()?;
+            let (hi, lo) = (bit_index(expr, &args[1])?, bit_index(expr, &args[2])?);
+            ensure!(hi >= lo, "Expected hi >= lo, found {} < {}", hi, lo);
+            from_bits(bits::field(x as u128, hi, lo))
+        }
+    );
+    def_fn!(
+        mask(n) = |expr, args| {
+            let n = expr.eval(&args[0])?.as_integer()?;
+            ensure!((0..128).contains(&n), "Expected 0 to 127 bits, found {}", n);
+            from_bits(bits::mask(n as u32))
+        }
+    );
+    def_fn!(
+        rotl(x, n, width) = |expr, args| {
+            let (x, width) = sized(expr, &args, 2)?;
+            let n = natural(expr, &args[1])?;
+            from_bits(bits::rotl(x, (n % width as u128) as u32, width))
+        }
+    );
+    def_fn!(
+        rotr(x, n, width) = |expr, args| {
+            let (x, width) = sized(expr, &args, 2)?;
+            let n = natural(expr, &args[1])?;
+            from_bits(bits::rotr(x, (n % width as u128) as u32, width))
+        }
+    );
+    def_fn!(
+        sext(x, width) = |expr, args| {
+            let (x, width) = sized(expr, &args, 1)?;
+            Ok(Value::int(bits::sext(x, width)))
+        }
+    );
+
+    // floats
+    def_fn!(
+        f32bits(x) = |expr, args| {
+            let x = expr.eval(&args[0])?.as_number()? as f32;
+            Ok(Value::int(x.to_bits() as i128))
+        }
+    );
+    def_fn!(
+        f64bits(x) = |expr, args| {
+            let x = expr.eval(&args[0])?.as_number()?;
+            Ok(Value::int(x.to_bits() as i128))
+        }
+    );
+    def_fn!(
+        fromf32bits(n) = |expr, args| {
+            let n = fit(expr.eval(&args[0])?.as_integer()?, 32)?;
+            Ok((f32::from_bits(n as u32) as f64).into())
+        }
+    );
+    def_fn!(
+        fromf64bits(n) = |expr, args| {
+            let n = fit(expr.eval(&args[0])?.as_integer()?, 64)?;
+            Ok(f64::from_bits(n as u64).into())
+        }
+    );
+    def_fn!(ulp(x) = |expr, args| { expr.eval(&args[0])?.map_number(float::ulp) });
+    def_fn!(
+        nextafter(x, y) = |expr, args| {
+            let x = expr.eval(&args[0])?.as_number()?;
+            let y = expr.eval(&args[1])?.as_number()?;
+            Ok(float::next_after(x, y).into())
+        }
+    );
+    def_fn!(
+        ulpdiff(a, b) = |expr, args| {
+            let a = expr.eval(&args[0])?.as_number()?;
+            let b = expr.eval(&args[1])?.as_number()?;
+            ensure!(!a.is_nan() && !b.is_nan(), "Expected numbers, found NaN");
+            Ok(Value::int(float::ulp_diff(a, b) as i128))
+        }
+    );
+
+    // characters
+    def_fn!(chr(n) = |expr, args| { to_char(expr.eval(&args[0])?) });
+    def_fn!(
+        ord(c) = |expr, args| {
+            let c = match expr.eval(&args[0])? {
+                Value::Char(c) => c,
+                // a string, which must contain exactly one (possibly multi-byte) character
+                v @ Value::List(_) => {
+                    let bytes = bytes(&v)?;
+                    let s = std::str::from_utf8(&bytes).context("String is not valid UTF-8")?;
+                    let mut chars = s.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) => c,
+                        _ => bail!("Expected exactly one character, found {}", v),
+                    }
+                }
+                v => bail!("Expected a character or string, found {}", v),
+            };
+            Ok((c as u32 as f64).into())
+        }
+    );
+
+    // checksums, over a list of bytes (or many bytes)
+    macro_rules! def_checksum {
+        ($name: ident => $f: expr) => {
+            def_fn!(
+                $name = |expr, args| {
+                    let data = byte_args(expr, &args)?;
+                    Ok(Value::int($f(&data) as i128))
+                }
+            )
+        };
+    }
+
+    def_checksum!(crc8 => |d| checksum::CRC8.checksum(d));
+    def_checksum!(crc8maxim => |d| checksum::CRC8_MAXIM.checksum(d));
+    def_checksum!(crc16 => |d| checksum::CRC16.checksum(d));
+    def_checksum!(crc16ccitt => |d| checksum::CRC16_CCITT.checksum(d));
+    def_checksum!(crc16xmodem => |d| checksum::CRC16_XMODEM.checksum(d));
+    def_checksum!(crc16kermit => |d| checksum::CRC16_KERMIT.checksum(d));
+    def_checksum!(crc16modbus => |d| checksum::CRC16_MODBUS.checksum(d));
+    def_checksum!(crc32 => |d| checksum::CRC32.checksum(d));
+    def_checksum!(crc32c => |d| checksum::CRC32C.checksum(d));
+    def_checksum!(adler32 => checksum::adler32);
+    def_checksum!(fnv1a => checksum::fnv1a32);
+    def_checksum!(fnv1a64 => checksum::fnv1a64);
+    def_checksum!(sum8 => |d| checksum::sum(d, 8));
+    def_checksum!(sum16 => |d| checksum::sum(d, 16));
+    def_checksum!(xor8 => checksum::xor);
+
+    // calculus, over an expression of a bound variable
+    def_fn!(
+        diff(expr, x, at) = |expr, args| {
+            let f = function_of(expr, &args[0], bound_variable(&args[1])?);
+            let at = expr.eval(&args[2])?.as_number()?;
+            Ok(calculus::derivative(f, at)?.into())
+        }
+    );
+    def_fn!(
+        integrate(expr, x, a, b) = |expr, args| {
+            let f = function_of(expr, &args[0], bound_variable(&args[1])?);
+            let a = expr.eval(&args[2])?.as_number()?;
+            let b = expr.eval(&args[3])?.as_number()?;
+            Ok(calculus::integrate(f, a, b)?.into())
+        }
+    );
     def_fn!(
-        gcd = |expr, args| {
-            let mut args = args.iter().map(|a| expr.eval(a));
-            let mut r = args.next().unwrap()?;
-            for n in args {
-                r = gcd(r, n?);
+        solve / 2 = |expr, args| {
+            match args.len() {
+                2 => {
+                    let (eqs, vars) = (equations(&args[0])?, variables(&args[1])?);
+                    match (eqs.as_slice(), vars.as_slice()) {
+                        ([eq], [var]) => polynomial_roots(expr, eq, var),
+                        _ => linear_system(expr, &eqs, &vars),
+                    }
+                }
+                3 => {
+                    let eq = AstStatement::from(&equation(&args[0])?);
+                    let f = function_of(expr, &eq, bound_variable(&args[1])?);
+                    let guess = expr.eval(&args[2])?.as_number()?;
+                    Ok(calculus::newton(f, guess)?.into())
+                }
+                _ => bail!("Expected 2 or 3 args: `solve(expr, x)` or `solve(expr, x, guess)`"),
+            }
+        }
+    );
+    def_fn!(
+        root(expr, x, a, b) = |expr, args| {
+            let f = function_of(expr, &args[0], bound_variable(&args[1])?);
+            let a = expr.eval(&args[2])?.as_number()?;
+            let b = expr.eval(&args[3])?.as_number()?;
+            Ok(calculus::brent(f, a, b)?.into())
+        }
+    );
+
+    // symbolic maths
+    def_fn!(simplify(expr) = |expr, args| { Ok(Expr::try_from(&args[0])?.simplify(expr).into()) });
+    def_fn!(
+        deriv / 2 = |expr, args| {
+            ensure!(
+                args.len() == 2 || args.len() == 3,
+                "Expected 2 or 3 args: `deriv(f, x)` or `deriv(f, x, at)`"
+            );
+            let var = bound_variable(&args[1])?;
+            let d = Expr::try_from(&args[0])?.derivative(var)?.simplify(expr);
+            match args.get(2) {
+                Some(at) => expr.eval_with(&AstStatement::from(&d), var, expr.eval(at)?),
+                None => Ok(d.into()),
             }
-            Ok(r)
+        }
+    );
+
+    // lists
+    def_fn!(
+        sum = |expr, args| {
+            if let Some(var) = loop_variable(expr, &args) {
+                return series(expr, var, &args[1..], Operator::Add, 0.0.into());
+            }
+            Ok(numbers(expr, &args)?
+                .into_iter()
+                .fold(0.0, |a, b| a + b)
+                .into())
+        }
+    );
+    def_fn!(
+        prod = |expr, args| {
+            if let Some(var) = loop_variable(expr, &args) {
+                return series(expr, var, &args[1..], Operator::Multiply, 1.0.into());
+            }
+            Ok(numbers(expr, &args)?.into_iter().product::<f64>().into())
+        }
+    );
+    def_stat!(min => |d: &[f64]| d.iter().copied().fold(f64::INFINITY, f64::min));
+    def_stat!(max => |d: &[f64]| d.iter().copied().fold(f64::NEG_INFINITY, f64::max));
+    def_stat!(mean => stats::mean);
+    def_fn!(len = |expr, args| { Ok((items(expr, &args)?.len() as f64).into()) });
+    def_fn!(
+        sort = |expr, args| {
+            let mut nums = numbers(expr, &args)?;
+            nums.sort_by(f64::total_cmp);
+            Ok(nums.into())
+        }
+    );
+
+    // statistics
+    def_stat!(median => stats::median);
+    def_stat!(mode => stats::mode);
+    def_stat!(range => |d: &[f64]| {
+        let (min, max) = d.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), n| {
+            (min.min(*n), max.max(*n))
+        });
+        max - min
+    });
+    def_stat!(pvariance => |d| stats::variance(d, false));
+    def_stat!(variance, 2 => |d| stats::variance(d, true));
+    def_stat!(pstddev => |d| stats::variance(d, false).sqrt());
+    def_stat!(stddev, 2 => |d| stats::variance(d, true).sqrt());
+    def_stat!(geomean => stats::geomean);
+    def_stat!(harmean => stats::harmean);
+    def_fn!(
+        percentile / 2 = |expr, args| {
+            ensure!(args.len() >= 2, "Expected 2 args: `percentile(data, p)`");
+            let (p, data) = args.split_last().expect("checked above");
+            let p = expr.eval(p)?.as_number()?;
+            ensure!(
+                (0.0..=100.0).contains(&p),
+                "Percentile must be in 0..=100: {}",
+                p
+            );
+            let nums = numbers(expr, data)?;
+            ensure!(
+                !nums.is_empty(),
+                "Expected at least 1 number: `percentile(data, p)`"
+            );
+            Ok(stats::percentile(&nums, p).into())
+        }
+    );
+    def_fn!(
+        zscore / 2 = |expr, args| {
+            ensure!(args.len() >= 2, "Expected 2 args: `zscore(x, data)`");
+            let nums = numbers(expr, &args[1..])?;
+            let (mean, stddev) = (stats::mean(&nums), stats::variance(&nums, false).sqrt());
+            ensure!(
+                stddev > 0.0,
+                "Expected data with a positive standard deviation, found {}",
+                stddev
+            );
+            expr.eval(&args[0])?.map_number(|x| (x - mean) / stddev)
+        }
+    );
+
+    // distributions
+    def_fn!(
+        normpdf = |expr, args| {
+            ensure!(
+                (1..=3).contains(&args.len()),
+                "Expected 1 to 3 args: `normpdf(x, mu = 0, sigma = 1)`"
+            );
+            let (mu, sigma) = normal_params(expr, &args)?;
+            expr.eval(&args[0])?
+                .map_number(|x| stats::normal_pdf(x, mu, sigma))
+        }
+    );
+    def_fn!(
+        normcdf = |expr, args| {
+            ensure!(
+                (1..=3).contains(&args.len()),
+                "Expected 1 to 3 args: `normcdf(x, mu = 0, sigma = 1)`"
+            );
+            let (mu, sigma) = normal_params(expr, &args)?;
+            expr.eval(&args[0])?
+                .map_number(|x| stats::normal_cdf(x, mu, sigma))
+        }
+    );
+    def_fn!(
+        norminv = |expr, args| {
+            ensure!(
+                (1..=3).contains(&args.len()),
+                "Expected 1 to 3 args: `norminv(p, mu = 0, sigma = 1)`"
+            );
+            let (mu, sigma) = normal_params(expr, &args)?;
+            let p = expr.eval(&args[0])?.as_number()?;
+            Ok(stats::normal_inv(p, mu, sigma)?.into())
+        }
+    );
+    def_fn!(
+        binompdf(k, n, p) = |expr, args| {
+            let (k, n, p) = (
+                count(expr, &args[0])?,
+                count(expr, &args[1])?,
+                prob(expr, &args[2])?,
+            );
+            Ok(stats::binomial_pdf(k, n, p).into())
+        }
+    );
+    def_fn!(
+        binomcdf(k, n, p) = |expr, args| {
+            let (k, n, p) = (
+                count(expr, &args[0])?,
+                count(expr, &args[1])?,
+                prob(expr, &args[2])?,
+            );
+            Ok(stats::binomial_cdf(k, n, p).into())
+        }
+    );
+    def_fn!(
+        binominv(q, n, p) = |expr, args| {
+            let (q, n, p) = (
+                prob(expr, &args[0])?,
+                count(expr, &args[1])?,
+                prob(expr, &args[2])?,
+            );
+            Ok((stats::binomial_inv(q, n, p)? as f64).into())
+        }
+    );
+    def_fn!(
+        poissonpdf(k, lambda) = |expr, args| {
+            let (k, lambda) = (count(expr, &args[0])?, rate(expr, &args[1])?);
+            Ok(stats::poisson_pdf(k, lambda).into())
+        }
+    );
+    def_fn!(
+        poissoncdf(k, lambda) = |expr, args| {
+            let (k, lambda) = (count(expr, &args[0])?, rate(expr, &args[1])?);
+            Ok(stats::poisson_cdf(k, lambda).into())
+        }
+    );
+    def_fn!(
+        poissoninv(q, lambda) = |expr, args| {
+            let (q, lambda) = (prob(expr, &args[0])?, rate(expr, &args[1])?);
+            Ok((stats::poisson_inv(q, lambda)? as f64).into())
+        }
+    );
+
+    // linear algebra
+    def_fn!(
+        transpose(A) = |expr, args| { Ok(expr.eval(&args[0])?.as_matrix()?.transpose().into()) }
+    );
+    def_fn!(det(A) = |expr, args| { Ok(expr.eval(&args[0])?.as_matrix()?.det()?.into()) });
+    def_fn!(inv(A) = |expr, args| { Ok(expr.eval(&args[0])?.as_matrix()?.inverse()?.into()) });
+    def_fn!(
+        identity(n) = |expr, args| {
+            let n = expr.eval(&args[0])?.as_integer()?;
+            ensure!(n > 0, "Expected a positive size, found {}", n);
+            Ok(Matrix::identity(usize::try_from(n)?)?.into())
+        }
+    );
+    def_fn!(
+        dot(a, b) = |expr, args| {
+            let a = expr.eval(&args[0])?.as_vector()?;
+            let b = expr.eval(&args[1])?.as_vector()?;
+            Ok(matrix::dot(&a, &b)?.into())
+        }
+    );
+    def_fn!(
+        cross(a, b) = |expr, args| {
+            let a = expr.eval(&args[0])?.as_vector()?;
+            let b = expr.eval(&args[1])?.as_vector()?;
+            Ok(matrix::cross(&a, &b)?.into())
+        }
+    );
+    def_fn!(
+        linsolve(A, b) = |expr, args| {
+            let a = expr.eval(&args[0])?;
+            let b = expr.eval(&args[1])?.as_vector()?;
+            Ok(a.as_matrix()?.solve(&b)?.into())
         }
     );
 
     map
 }
 
-fn gcd(mut a: f64, mut b: f64) -> f64 {
-    if a == 0.0 {
-        return b;
+/// Get the name of the variable in `arg`, which must be just a variable, like the `x` in
+/// `diff(x ** 2, x, 3)`
+fn bound_variable(arg: &AstStatement) -> anyhow::Result<&str> {
+    match arg {
+        AstStatement::Variable(v) => Ok(v),
+        AstStatement::InfixExpression(e) if e.len() == 1 => bound_variable(&e[0]),
+        _ => bail!("Expected a variable name, found '{}'", arg),
+    }
+}
+
+/// The loop variable of a comprehension like `sum(i, 1, 100, i ** 2)`, which has 4 args where the
+/// first is just a name.  Names which already have a value, like `pi` or `_`, are summed as
+/// values instead, so `sum(pi, 1, 2, 3)` is `pi + 6`.
+fn loop_variable<'a>(expr: &AstEvaluator, args: &'a [AstStatement]) -> Option<&'a str> {
+    match args {
+        [var, _, _, _] => bound_variable(var)
+            .ok()
+            .filter(|v| expr.get_variable(v.to_string()).is_err()),
+        _ => None,
+    }
+}
+
+/// The most terms that `sum` and `prod` will evaluate, so that a typo doesn't hang the REPL
+const MAX_TERMS: i128 = 1_000_000;
+
+/// Combine `body` with `op` for every integer value of `var` within the bounds `args[0]` and
+/// `args[1]` (inclusive), starting from `init`
+fn series(
+    expr: &AstEvaluator,
+    var: &str,
+    args: &[AstStatement],
+    op: Operator,
+    init: Value,
+) -> anyhow::Result<Value> {
+    let from = expr.eval(&args[0])?.as_integer()?;
+    let to = expr.eval(&args[1])?.as_integer()?;
+    ensure!(
+        to.saturating_sub(from) < MAX_TERMS,
+        "Too many terms from {} to {} (at most {} are allowed)",
+        from,
+        to,
+        MAX_TERMS
+    );
+
+    (from..=to).try_fold(init, |acc, i| {
+        let term = expr.eval_with(&args[2], var, Value::int(i))?;
+        op.eval(acc, term)
+            .with_context(|| format!("Evaluating term {} = {}", var, i))
+    })
+}
+
+/// Get the names of the variables in `arg`, which is either a variable or a list of them:
+/// `[x, y]`
+fn variables(arg: &AstStatement) -> anyhow::Result<Vec<&str>> {
+    match arg {
+        AstStatement::InfixExpression(e) if e.len() == 1 => variables(&e[0]),
+        AstStatement::List(items) => items.iter().map(bound_variable).collect(),
+        arg => Ok(vec![bound_variable(arg)?]),
+    }
+}
+
+/// Read `arg` as an equation which is true when it's zero, so `a = b` becomes `a - b`
+fn equation(arg: &AstStatement) -> anyhow::Result<Expr> {
+    Ok(match Expr::try_from(arg)? {
+        Expr::Binary(Operator::Equals, a, b) => Expr::Binary(Operator::Subtract, a, b),
+        e => e,
+    })
+}
+
+/// Get the equations in `arg`, which is either an equation or a list of them:
+/// `[x + y = 3, x - y = 1]`
+fn equations(arg: &AstStatement) -> anyhow::Result<Vec<Expr>> {
+    match arg {
+        AstStatement::InfixExpression(e) if e.len() == 1 => equations(&e[0]),
+        AstStatement::List(items) => items.iter().map(equation).collect(),
+        arg => Ok(vec![equation(arg)?]),
+    }
+}
+
+/// Find every root of the polynomial `eq` in `var`
+fn polynomial_roots(expr: &AstEvaluator, eq: &Expr, var: &str) -> anyhow::Result<Value> {
+    let terms = eq.polynomial(&[var], expr)?;
+    let degree = terms.iter().map(|(p, _)| p[0] as usize).max().unwrap_or(0);
+    ensure!(
+        degree <= polynomial::MAX_DEGREE,
+        "'{}' has degree {}, but only polynomials up to degree {} can be solved",
+        eq,
+        degree,
+        polynomial::MAX_DEGREE
+    );
+    let mut coefs = vec![0.0; degree + 1];
+    for (powers, c) in terms {
+        coefs[powers[0] as usize] += c;
     }
-    while b > 0.0 {
-        let r = a % b;
-        a = b;
-        b = r;
+    Ok(Value::List(
+        polynomial::roots(&coefs)?
+            .into_iter()
+            .map(Value::from)
+            .collect(),
+    ))
+}
+
+/// Solve a system of linear equations for `vars`
+fn linear_system(expr: &AstEvaluator, eqs: &[Expr], vars: &[&str]) -> anyhow::Result<Value> {
+    ensure!(
+        eqs.len() == vars.len(),
+        "Expected as many equations as variables, found {} and {}",
+        eqs.len(),
+        vars.len()
+    );
+
+    let n = vars.len();
+    let mut a = vec![0.0; n * n];
+    let mut b = vec![0.0; n];
+    for (row, eq) in eqs.iter().enumerate() {
+        for (powers, c) in eq.polynomial(vars, expr)? {
+            match powers.iter().sum::<u32>() {
+                0 => b[row] -= c,
+                1 => a[row * n + powers.iter().position(|p| *p == 1).expect("sum is 1")] += c,
+                _ => bail!("'{}' is not linear in {}", eq, vars.join(", ")),
+            }
+        }
+    }
+    Ok(Matrix::new(n, n, a).solve(&b)?.into())
+}
+
+/// Turn `body` into a function of the variable `var`
+fn function_of<'a>(
+    expr: &'a AstEvaluator,
+    body: &'a AstStatement,
+    var: &'a str,
+) -> impl Fn(f64) -> anyhow::Result<f64> + 'a {
+    move |x| expr.eval_with(body, var, x.into())?.as_number()
+}
+
+/// Evaluate the args of a function which accepts either a single list or many values, so that
+/// `f([1, 2, 3])` and `f(1, 2, 3)` are the same.
+fn items(expr: &AstEvaluator, args: &[AstStatement]) -> anyhow::Result<Vec<Value>> {
+    let mut values = args
+        .iter()
+        .map(|a| expr.eval(a))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if let [Value::List(_) | Value::Matrix(_)] = values.as_slice() {
+        let Some(Value::List(items)) = values.pop().map(Value::into_rows) else {
+            unreachable!("checked by the above pattern");
+        };
+        Ok(items)
+    } else {
+        Ok(values)
+    }
+}
+
+/// Evaluate the optional number arg at `i`, using `default` if it was not given
+fn arg_or(
+    expr: &AstEvaluator,
+    args: &[AstStatement],
+    i: usize,
+    default: f64,
+) -> anyhow::Result<f64> {
+    args.get(i)
+        .map_or(Ok(default), |a| expr.eval(a)?.as_number())
+}
+
+/// Multiply `factors`, which are all at least 1, stopping once the product overflows to infinity
+/// so that huge inputs don't loop for every factor
+fn product<I>(factors: I) -> f64
+where
+    I: Iterator<Item = f64>,
+{
+    let mut out = 1.0;
+    for n in factors {
+        out *= n;
+        if out.is_infinite() {
+            break;
+        }
+    }
+    out
+}
+
+/// Evaluate the optional mean and standard deviation of a normal distribution, `args[1]` and
+/// `args[2]`
+fn normal_params(expr: &AstEvaluator, args: &[AstStatement]) -> anyhow::Result<(f64, f64)> {
+    let (mu, sigma) = (arg_or(expr, args, 1, 0.0)?, arg_or(expr, args, 2, 1.0)?);
+    ensure!(
+        sigma > 0.0,
+        "Standard deviation must be positive: {}",
+        sigma
+    );
+    Ok((mu, sigma))
+}
+
+/// Evaluate an arg which must be a non-negative integer
+fn count(expr: &AstEvaluator, arg: &AstStatement) -> anyhow::Result<u64> {
+    let n = expr.eval(arg)?.as_integer()?;
+    ensure!(n >= 0, "Expected a non-negative integer, found {}", n);
+    Ok(n as u64)
+}
+
+/// Evaluate an arg which must be a non-negative integer, keeping its exact value
+fn natural(expr: &AstEvaluator, arg: &AstStatement) -> anyhow::Result<u128> {
+    let n = expr.eval(arg)?.as_integer()?;
+    ensure!(n >= 0, "Expected a non-negative integer, found {}", n);
+    Ok(n as u128)
+}
+
+/// Evaluate `args[0]` as an integer which fits in the width given by `args[width]` (64 bits if
+/// it's missing), returning its bits and the width
+fn sized(expr: &AstEvaluator, args: &[AstStatement], width: usize) -> anyhow::Result<(u128, u32)> {
+    let width = match args.get(width) {
+        Some(w) => bits::check_width(expr.eval(w)?.as_integer()?)?,
+        None => 64,
+    };
+    let x = fit(expr.eval(&args[0])?.as_integer()?, width)?;
+    Ok((x, width))
+}
+
+/// Get the bits of `x` within `width` bits, failing if `x` does not fit as either a signed or an
+/// unsigned integer.
+fn fit(x: i128, width: u32) -> anyhow::Result<u128> {
+    let fits = if width == 128 {
+        true
+    } else if x < 0 {
+        x >= -(1i128 << (width - 1))
+    } else {
+        // unsigned, so that `1 << 127` doesn't overflow
+        (x as u128) < 1u128 << width
+    };
+    ensure!(fits, "{} does not fit in {} bits", x, width);
+    Ok(bits::truncate(x, width))
+}
+
+/// Evaluate an arg which must be the index of a bit in a 128-bit integer
+fn bit_index(expr: &AstEvaluator, arg: &AstStatement) -> anyhow::Result<u32> {
+    let n = expr.eval(arg)?.as_integer()?;
+    ensure!(
+        (0..128).contains(&n),
+        "Bit index must be in 0..128, found {}",
+        n
+    );
+    Ok(n as u32)
+}
+
+/// Convert the result of a bit manipulation function into a value
+fn from_bits(x: u128) -> anyhow::Result<Value> {
+    Ok(Value::int(x.try_into().context(
+        "Result does not fit in a signed 128-bit integer",
+    )?))
+}
+
+/// Convert a code point into a character, or every code point in a list
+fn to_char(v: Value) -> anyhow::Result<Value> {
+    match v {
+        Value::List(items) => Ok(Value::List(
+            items
+                .into_iter()
+                .map(to_char)
+                .collect::<anyhow::Result<_>>()?,
+        )),
+        v => {
+            let n = v.as_integer()?;
+            u32::try_from(n)
+                .ok()
+                .and_then(char::from_u32)
+                .map(Value::Char)
+                .with_context(|| format!("{} is not a valid character", n))
+        }
+    }
+}
+
+/// Get the bytes in a list of integers, like a string
+fn bytes(v: &Value) -> anyhow::Result<Vec<u8>> {
+    let Value::List(items) = v else {
+        bail!("Expected a list of bytes, found {}", v);
+    };
+    items
+        .iter()
+        .map(|b| {
+            b.to_exact_int()
+                .and_then(|b| u8::try_from(b).ok())
+                .with_context(|| format!("Expected a byte (0 to 255), found {}", b))
+        })
+        .collect()
+}
+
+/// Evaluate the args of a function into bytes, accepting a list (or string) like [`items`]
+fn byte_args(expr: &AstEvaluator, args: &[AstStatement]) -> anyhow::Result<Vec<u8>> {
+    bytes(&Value::List(items(expr, args)?))
+}
+
+/// Evaluate the args of a function into exact integers, accepting a list like [`items`]
+fn integers(expr: &AstEvaluator, args: &[AstStatement]) -> anyhow::Result<Vec<i128>> {
+    items(expr, args)?.iter().map(Value::as_integer).collect()
+}
+
+/// Evaluate an arg which must be a probability in `0..=1`
+fn prob(expr: &AstEvaluator, arg: &AstStatement) -> anyhow::Result<f64> {
+    let p = expr.eval(arg)?.as_number()?;
+    ensure!(
+        (0.0..=1.0).contains(&p),
+        "Probability must be in 0..=1: {}",
+        p
+    );
+    Ok(p)
+}
+
+/// Evaluate an arg which must be a non-negative finite rate
+fn rate(expr: &AstEvaluator, arg: &AstStatement) -> anyhow::Result<f64> {
+    let r = expr.eval(arg)?.as_number()?;
+    ensure!(
+        r >= 0.0 && r.is_finite(),
+        "Rate must be non-negative: {}",
+        r
+    );
+    Ok(r)
+}
+
+/// Evaluate the args of a function into a flat list of numbers, flattening any lists
+fn numbers(expr: &AstEvaluator, args: &[AstStatement]) -> anyhow::Result<Vec<f64>> {
+    let mut out = Vec::new();
+    for a in args {
+        expr.eval(a)?.flatten_into(&mut out)?;
     }
-    return a;
+    Ok(out)
 }
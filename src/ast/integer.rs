@@ -0,0 +1,274 @@
+/// Bases used for Miller-Rabin, these are enough to be deterministic for every
+/// `n < 3317044064679887385961981` (about `3.3 * 10^24`).  No set of bases is known to be enough
+/// for every `u128`, so above that a composite could be reported as prime, although none are
+/// known.
+const WITNESSES: [u128; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// Find the gcd of two integers, which is always non-negative
+pub fn gcd(a: i128, b: i128) -> u128 {
+    gcd_unsigned(a.unsigned_abs(), b.unsigned_abs())
+}
+
+fn gcd_unsigned(mut a: u128, mut b: u128) -> u128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Find the lcm of two integers, or `None` if it overflows
+pub fn lcm(a: i128, b: i128) -> Option<i128> {
+    if a == 0 || b == 0 {
+        return Some(0);
+    }
+    let g: i128 = gcd(a, b).try_into().ok()?;
+    (a / g).checked_mul(b).and_then(i128::checked_abs)
+}
+
+/// `n!`, or `None` if it overflows
+pub fn factorial(n: u128) -> Option<i128> {
+    (2..=n).try_fold(1i128, |acc, i| acc.checked_mul(i.try_into().ok()?))
+}
+
+/// The number of ways to choose `k` items from `n` (`nCr`), or `None` if it overflows
+pub fn choose(n: u128, k: u128) -> Option<i128> {
+    if k > n {
+        return Some(0);
+    }
+    let k = k.min(n - k);
+    let mut c: u128 = 1;
+    for i in 1..=k {
+        // c * (n - k + i) is always divisible by i, so divide out the common factor first to keep
+        // the intermediate value as small as possible
+        let g = gcd_unsigned(c, i);
+        c = (c / g).checked_mul((n - k + i) / (i / g))?;
+    }
+    c.try_into().ok()
+}
+
+/// The number of ordered arrangements of `k` items from `n` (`nPr`), or `None` if it overflows
+pub fn permute(n: u128, k: u128) -> Option<i128> {
+    if k > n {
+        return Some(0);
+    }
+    (n - k + 1..=n).try_fold(1i128, |acc, i| acc.checked_mul(i.try_into().ok()?))
+}
+
+/// The `n`th fibonacci number, or `None` if it overflows
+pub fn fib(n: u128) -> Option<i128> {
+    let (mut a, mut b) = (0i128, 1i128);
+    for _ in 0..n {
+        (a, b) = (b, a.checked_add(b)?);
+    }
+    Some(a)
+}
+
+/// `(a + b) % m` without overflowing
+fn add_mod(a: u128, b: u128, m: u128) -> u128 {
+    if a >= m - b {
+        a - (m - b)
+    } else {
+        a + b
+    }
+}
+
+/// `(a * b) % m` without overflowing
+fn mul_mod(a: u128, b: u128, m: u128) -> u128 {
+    let (mut a, mut b) = (a % m, b % m);
+    if m <= u64::MAX as u128 {
+        return a * b % m;
+    }
+
+    let mut out = 0;
+    while b > 0 {
+        if b & 1 == 1 {
+            out = add_mod(out, a, m);
+        }
+        a = add_mod(a, a, m);
+        b >>= 1;
+    }
+    out
+}
+
+/// `(b ** e) % m`
+pub fn pow_mod(b: u128, mut e: u128, m: u128) -> u128 {
+    let mut b = b % m;
+    let mut out = 1 % m;
+    while e > 0 {
+        if e & 1 == 1 {
+            out = mul_mod(out, b, m);
+        }
+        b = mul_mod(b, b, m);
+        e >>= 1;
+    }
+    out
+}
+
+/// Find `x` such that `(a * x) % m == 1`, or `None` if `a` and `m` are not coprime
+pub fn mod_inv(a: i128, m: i128) -> Option<i128> {
+    let (mut r0, mut r1) = (a.rem_euclid(m), m);
+    let (mut s0, mut s1) = (1i128, 0i128);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (s0, s1) = (s1, s0 - q * s1);
+    }
+    (r0 == 1).then(|| s0.rem_euclid(m))
+}
+
+/// Check if `n` is prime using Miller-Rabin, which is exact for `n` below about `3.3 * 10^24` and
+/// probabilistic above that (see [`WITNESSES`])
+pub fn is_prime(n: u128) -> bool {
+    if n < 2 {
+        return false;
+    }
+    for p in WITNESSES {
+        if n % p == 0 {
+            return n == p;
+        }
+    }
+
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    'witness: for a in WITNESSES {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            continue;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                continue 'witness;
+            }
+        }
+        return false;
+    }
+    true
+}
+
+/// Find the smallest prime which is greater than `n`
+pub fn next_prime(n: u128) -> u128 {
+    let mut n = n + 1;
+    while !is_prime(n) {
+        n += 1;
+    }
+    n
+}
+
+/// Find the prime factors of `n` (with repeats) in ascending order.  Like [`is_prime`], the
+/// factors are only certain to be prime below about `3.3 * 10^24`.
+///
+/// Note: `n` _must_ be greater than 0
+pub fn factor(mut n: u128) -> Vec<u128> {
+    assert!(n > 0, "cannot factor 0");
+    let mut out = Vec::new();
+    for p in 2..1000 {
+        while n % p == 0 {
+            out.push(p);
+            n /= p;
+        }
+    }
+    if n > 1 {
+        factor_large(n, &mut out);
+    }
+    out.sort_unstable();
+    out
+}
+
+/// Factor `n`, which has no small factors, using Pollard's rho
+fn factor_large(n: u128, out: &mut Vec<u128>) {
+    if is_prime(n) {
+        out.push(n);
+        return;
+    }
+
+    for c in 1.. {
+        let f = |x| add_mod(mul_mod(x, x, n), c, n);
+        let (mut x, mut y, mut d) = (2, 2, 1);
+        while d == 1 {
+            x = f(x);
+            y = f(f(y));
+            d = gcd_unsigned(x.abs_diff(y), n);
+        }
+        if d != n {
+            factor_large(d, out);
+            factor_large(n / d, out);
+            return;
+        }
+    }
+}
+
+/// Euler's totient: the count of integers in `1..=n` which are coprime to `n`
+pub fn totient(n: u128) -> u128 {
+    let mut factors = factor(n);
+    factors.dedup();
+    factors.into_iter().fold(n, |acc, p| acc / p * (p - 1))
+}
+
+/// Division which rounds towards negative infinity, or `None` if it overflows
+pub fn floor_div(a: i128, b: i128) -> Option<i128> {
+    let q = a.checked_div(b)?;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Some(q - 1)
+    } else {
+        Some(q)
+    }
+}
+
+/// The remainder of [`floor_div`], which always has the same sign as `b`, or `None` if it
+/// overflows
+pub fn floor_mod(a: i128, b: i128) -> Option<i128> {
+    let r = a.checked_rem(b)?;
+    if r != 0 && (r < 0) != (b < 0) {
+        Some(r + b)
+    } else {
+        Some(r)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn primes() {
+        assert!(is_prime(2));
+        assert!(is_prime(1_000_000_007));
+        assert!(!is_prime(561)); // carmichael number
+        assert!(is_prime(18446744073709551557)); // largest prime below 2^64
+        assert!(is_prime(170141183460469231731687303715884105727)); // 2^127 - 1
+        assert_eq!(next_prime(13), 17);
+        assert_eq!(factor(360), vec![2, 2, 2, 3, 3, 5]);
+        assert_eq!(
+            factor(1_000_000_007 * 998_244_353),
+            vec![998_244_353, 1_000_000_007]
+        );
+        assert_eq!(totient(36), 12);
+    }
+
+    #[test]
+    fn combinatorics() {
+        assert_eq!(factorial(20), Some(2432902008176640000));
+        assert_eq!(factorial(40), None);
+        assert_eq!(choose(100, 50), Some(100891344545564193334812497256));
+        assert_eq!(permute(5, 2), Some(20));
+        assert_eq!(fib(100), Some(354224848179261915075));
+        assert_eq!(lcm(-4, 6), Some(12));
+        assert_eq!(lcm(i128::MIN, 1), None);
+    }
+
+    #[test]
+    fn modular() {
+        assert_eq!(pow_mod(4, 13, 497), 445);
+        // fermat's little theorem, using a modulus which requires the slow `mul_mod`
+        let p = 170141183460469231731687303715884105727;
+        assert_eq!(pow_mod(3, p - 1, p), 1);
+        assert_eq!(mod_inv(3, 11), Some(4));
+        assert_eq!(mod_inv(2, 4), None);
+        assert_eq!(floor_div(-7, 2), Some(-4));
+        assert_eq!(floor_mod(-7, 2), Some(1));
+        assert_eq!(floor_mod(7, -2), Some(-1));
+        assert_eq!(floor_div(i128::MIN, -1), None);
+        assert_eq!(floor_mod(i128::MIN, -1), None);
+    }
+}
@@ -1,9 +1,13 @@
+use super::value::MAX_EXACT_F64;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum NumberKind {
     /// 123
     Dec,
     /// 0x123
     Hex,
+    /// 0o123
+    Oct,
     /// 0b101
     Bin,
 }
@@ -14,42 +18,120 @@ impl NumberKind {
         match self {
             Self::Dec => 10,
             Self::Hex => 16,
+            Self::Oct => 8,
             Self::Bin => 2,
         }
     }
 
-    /// Parse a character from the given numberkind's radix
-    pub fn parse(&self, s: &str) -> Option<f64> {
+    /// Check if `c` starts the exponent of a number of this kind: `1e3` or `0x1p3`
+    fn is_exponent(&self, c: char) -> bool {
         match self {
-            Self::Dec => s.parse().ok(),
-            _ => {
-                if let Some((whole, frac)) = s.split_once('.') {
-                    let radix = self.radix();
-                    let num = u64::from_str_radix(whole, radix).ok()? as f64;
-                    let mut frac = u64::from_str_radix(frac, radix).ok()? as f64;
-                    while frac >= 1.0 {
-                        frac /= radix as f64;
-                    }
-                    Some(num + frac)
-                } else {
-                    Some(u64::from_str_radix(s, self.radix()).ok()? as f64)
+            Self::Dec => matches!(c, 'e' | 'E'),
+            Self::Hex => matches!(c, 'p' | 'P'),
+            Self::Oct | Self::Bin => false,
+        }
+    }
+
+    /// Remove the separators from `s`, checking that the rest are valid digits for this radix
+    fn digits(&self, s: &str) -> Result<String, String> {
+        let digits = remove_separators(s)?;
+        match digits.chars().find(|c| !c.is_digit(self.radix())) {
+            Some(c) => Err(format!("Invalid digit '{}' for base {}", c, self.radix())),
+            None => Ok(digits),
+        }
+    }
+
+    /// Parse a number literal of this kind, without its prefix (`0x`, ...), into a token
+    fn parse(&self, literal: &str) -> Result<TokenKind, String> {
+        let (mantissa, exponent) = match literal.find(|c| self.is_exponent(c)) {
+            Some(i) => (&literal[..i], Some(&literal[i + 1..])),
+            None => (literal, None),
+        };
+        let (whole, frac) = match mantissa.split_once('.') {
+            Some((_, frac)) if frac.contains('.') => return Err("Found more than one '.'".into()),
+            Some((whole, frac)) => (self.digits(whole)?, Some(self.digits(frac)?)),
+            None => (self.digits(mantissa)?, None),
+        };
+        if whole.is_empty() && frac.as_ref().map_or(true, String::is_empty) {
+            return Err("Expected digits".into());
+        }
+
+        let exponent = match exponent {
+            Some(e) => {
+                let e = remove_separators(e)?;
+                let digits = e.strip_prefix(&['+', '-'][..]).unwrap_or(&e);
+                if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+                    return Err("Expected digits in the exponent".into());
+                }
+                e.parse::<i32>()
+                    .map_err(|_| "Exponent is too large".to_string())?
+            }
+            None => 0,
+        };
+
+        if frac.is_none() && exponent == 0 {
+            // Keep integers which are too large to be stored in an f64 exact
+            if let Ok(n) = i128::from_str_radix(&whole, self.radix()) {
+                if n > MAX_EXACT_F64 {
+                    return Ok(TokenKind::Integer(n, *self));
                 }
             }
         }
+
+        let n = match self {
+            Self::Dec => format!("{}.{}e{}", whole, frac.unwrap_or_default(), exponent)
+                .parse()
+                .map_err(|_| "Invalid number".to_string())?,
+            _ => {
+                let radix = self.radix() as f64;
+                let whole = match u128::from_str_radix(&whole, self.radix()) {
+                    Ok(n) => n as f64,
+                    // the digits are already checked, so this can only be too large for a u128
+                    Err(_) => whole.chars().fold(0.0, |acc, c| {
+                        acc * radix + c.to_digit(self.radix()).expect("checked above") as f64
+                    }),
+                };
+                let mut place = 1.0;
+                let mut value = 0.0;
+                for c in frac.unwrap_or_default().chars() {
+                    place /= radix;
+                    value += c.to_digit(self.radix()).expect("checked above") as f64 * place;
+                }
+                (whole + value) * 2f64.powi(exponent)
+            }
+        };
+        Ok(TokenKind::Number(n, *self))
     }
+}
 
-    /// Check if a char is a valid digit for this radix
-    pub fn is_valid_digit(&self, c: char) -> bool {
-        c == '.' || c.is_digit(self.radix())
+/// Remove the separators from part of a number, which must each be between two digits or after
+/// the start: `1_000` and `0x_ff`, but not `1_` or `1__0`
+fn remove_separators(s: &str) -> Result<String, String> {
+    if s.ends_with('_') {
+        return Err("Expected a digit after '_'".into());
+    }
+    if s.contains("__") {
+        return Err("Found repeated '_'".into());
     }
+    Ok(s.chars().filter(|c| *c != '_').collect())
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenKind {
     /// 123
+    /// 1.5e-3
     /// 0x123
-    /// 0b00010101
+    /// 0o755
+    /// 0b0001_0101
     Number(f64, NumberKind),
+    /// An integer literal which is too large to be stored exactly in an f64
+    /// 0xffffffffffffffff
+    Integer(i128, NumberKind),
+    /// 'a'
+    /// '\n'
+    Char(char),
+    /// "abc", as the bytes of its UTF-8 encoding
+    Str(Vec<u8>),
     Ident(String),
     /// +
     Plus,
@@ -65,20 +147,24 @@ pub enum TokenKind {
     Exponent,
     // /// ~
     // Tilda,
-    // /// !
-    // Bang,
+    /// !
+    Bang,
     /// ^
     Carrot,
     /// &
     Ampersand,
     /// |
     Pipe,
+    /// ..
+    DotDot,
 
     /// ,
     Comma,
+    /// ;
+    Semicolon,
 
-    // /// =
-    // Assign,
+    /// =
+    Equals,
     /// (
     LeftParen,
     /// )
@@ -100,8 +186,8 @@ pub enum TokenKind {
 
     /// End of file
     Eof,
-    /// Invalid Token
-    Invalid,
+    /// Invalid Token, with the reason that it is invalid
+    Invalid(String),
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -115,19 +201,14 @@ impl TextSpan {
         Self { start, end }
     }
 
-    /// Print a text span highlighting the correct region in the string
-    pub fn print(&self, s: &str) {
-        const MIN_BEGIN: usize = 20;
+    /// The index of the first character in the span
+    pub fn start(&self) -> usize {
+        self.start
+    }
 
-        let chars = s.chars();
-        let chars = chars.skip(std::cmp::max(MIN_BEGIN, self.start) - MIN_BEGIN);
-        let chars = chars.take(self.end - self.start + MIN_BEGIN * 2);
-        println!("{}", chars.collect::<String>());
-        println!(
-            "{}{}",
-            " ".repeat(std::cmp::min(MIN_BEGIN, self.start)),
-            "^".repeat(self.end - self.start)
-        );
+    /// The index just after the last character in the span
+    pub fn end(&self) -> usize {
+        self.end
     }
 }
 
@@ -143,17 +224,53 @@ impl Token {
     }
 }
 
-/// Convert a character to a number by subtracting '0'
-///
-/// Note: `c` _must_ be valid ascii between '0'..='9' for a correct result
-fn char_to_num(c: char) -> f64 {
-    (c as u8 - b'0') as f64
+/// A comment which the lexer skipped
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub span: TextSpan,
+    /// Whether it's a `# comment` or `// comment`, which runs until the end of the line
+    pub line: bool,
+    /// Whether it's a `/* block comment */` which is missing its `*/`
+    pub unterminated: bool,
+}
+
+/// Find every comment in `input`, ignoring anything which only looks like a comment because it's
+/// inside a string or character literal
+pub fn comments(input: &str) -> Vec<Comment> {
+    let mut lexer = Lexer::new(input);
+    lexer.by_ref().for_each(drop);
+    lexer.comments
+}
+
+/// Whether `input` ends inside a `/* block comment */`, which carries on into the next line
+pub fn ends_in_comment(input: &str) -> bool {
+    comments(input).last().is_some_and(|c| c.unterminated)
+}
+
+/// Quote `bytes` as a string literal which lexes back into the same bytes
+pub fn quote_bytes(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => format!("{:?}", s),
+        Err(_) => format!("\"{}\"", bytes.escape_ascii()),
+    }
+}
+
+/// One character within a character or string literal
+enum LiteralChar {
+    Char(char),
+    /// A raw byte from a `\x` escape
+    Byte(u8),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Lexer {
     input: Vec<char>,
     index: usize,
+    /// Whether the last token could be the end of an operand, in which case a `-` is always
+    /// subtraction rather than the sign of a number: `1-2`
+    after_operand: bool,
+    /// The comments which have been skipped so far
+    comments: Vec<Comment>,
 }
 
 impl Lexer {
@@ -165,6 +282,8 @@ impl Lexer {
         Self {
             input: input.as_ref().chars().collect(),
             index: 0,
+            after_operand: false,
+            comments: Vec::new(),
         }
     }
 
@@ -177,90 +296,140 @@ impl Lexer {
         }
     }
 
-    /// Consume one number from the input and return it as an f64 and NumberKind
-    fn take_num(&mut self) -> Option<(f64, NumberKind)> {
-        // Match the first digit
-        let Some(c) = self.current_char() else {
-            return None;
-        };
+    /// Check if the two characters at `offset` from the current index are a range (`..`)
+    fn range_at(&self, offset: usize) -> bool {
+        self.input.get(self.index + offset) == Some(&'.')
+            && self.input.get(self.index + offset + 1) == Some(&'.')
+    }
 
-        //eprintln!("lead digit: '{}'", c);
-        let lead_digit = if c == '.' || c.is_ascii_digit() {
-            c
-        } else {
-            //eprintln!("No lead digit");
-            return None;
+    /// Check if the next token is `**`, skipping any whitespace before it
+    fn exponent_follows(&self) -> bool {
+        let mut rest = self.input[self.index.min(self.input.len())..]
+            .iter()
+            .skip_while(|c| c.is_whitespace());
+        rest.next() == Some(&'*') && rest.next() == Some(&'*')
+    }
+
+    /// Consume one number literal from the input.  Separators (`_`) may be used in every base,
+    /// decimal numbers may have an exponent (`1.5e-3`), and hex numbers may have a binary
+    /// exponent (`0x1.8p3`).
+    fn take_num(&mut self) -> Result<TokenKind, String> {
+        let start = self.index;
+        let kind = match (self.current_char(), self.peek_char()) {
+            (Some('0'), Some('x' | 'X')) => NumberKind::Hex,
+            (Some('0'), Some('o' | 'O')) => NumberKind::Oct,
+            (Some('0'), Some('b' | 'B')) => NumberKind::Bin,
+            _ => NumberKind::Dec,
         };
+        if kind != NumberKind::Dec {
+            self.index += 2;
+        }
 
-        let mut kind = NumberKind::Dec;
-        let mut number = String::new();
+        // Take everything which could be part of the literal, so that a malformed literal is
+        // reported as a whole rather than being split into several tokens
+        let mut literal = String::new();
+        while let Some(c) = self.current_char() {
+            let exponent_sign = matches!(c, '+' | '-')
+                && literal.chars().last().is_some_and(|e| kind.is_exponent(e));
+            let point = c == '.' && !self.range_at(0);
+            if !(c.is_ascii_alphanumeric() || c == '_' || point || exponent_sign) {
+                break;
+            }
+            literal.push(c);
+            self.index += 1;
+        }
 
-        // Match the second char (might not be a digit if hex or bin)
-        if let Some(c) = self.peek_char() {
-            if lead_digit == '0' {
-                match c {
-                    'x' => {
-                        kind = NumberKind::Hex;
-                    }
-                    'b' => {
-                        kind = NumberKind::Bin;
-                    }
-                    '.' | '0'..='9' => {
-                        // If we want octal support, add it here:
-                        // kind = NumberKind::Oct;
-                        number.push(lead_digit);
-                        number.push(c);
-                    }
-                    // End of number
-                    _ => {
-                        self.take_char();
-                        return Some((0.0, kind));
-                    }
+        kind.parse(&literal).map_err(|e| {
+            let text: String = self.input[start..self.index].iter().collect();
+            format!("{} in number '{}'", e, text)
+        })
+    }
+
+    /// Take one character from a character or string literal, handling any escape
+    fn take_literal_char(&mut self) -> Result<LiteralChar, String> {
+        let c = self.take_char().ok_or("Unterminated literal")?;
+        if c != '\\' {
+            return Ok(LiteralChar::Char(c));
+        }
+
+        let c = self.take_char().ok_or("Unterminated literal")?;
+        Ok(LiteralChar::Char(match c {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            '0' => '\0',
+            '\\' | '\'' | '"' => c,
+            'x' => {
+                let hex: String = (0..2).filter_map(|_| self.take_char()).collect();
+                let byte = u8::from_str_radix(&hex, 16)
+                    .map_err(|_| format!("Invalid escape '\\x{}'", hex))?;
+                return Ok(LiteralChar::Byte(byte));
+            }
+            'u' => {
+                if self.take_char() != Some('{') {
+                    return Err("Expected '{' after '\\u'".into());
                 }
-                self.take_char();
-            } else {
-                number.push(lead_digit);
-                self.take_char();
-                if kind.is_valid_digit(c) {
-                    //eprintln!("'{}' is valid", c);
-                    number.push(c);
-                } else {
-                    //eprintln!("returning {}", number);
-                    return Some((kind.parse(&number)?, kind));
+                let mut hex = String::new();
+                loop {
+                    match self.take_char() {
+                        Some('}') => break,
+                        Some(c) => hex.push(c),
+                        None => return Err("Unterminated escape '\\u{'".into()),
+                    }
                 }
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| format!("Invalid unicode escape '\\u{{{}}}'", hex))?
             }
-        } else {
-            self.take_char();
-            return Some((char_to_num(lead_digit), kind));
+            c => return Err(format!("Unknown escape '\\{}'", c)),
+        }))
+    }
+
+    /// Consume a character literal: `'a'`
+    fn take_char_literal(&mut self) -> Result<TokenKind, String> {
+        self.take_char(); // the opening quote
+        if self.current_char() == Some('\'') {
+            return Err("Empty character literal".into());
         }
 
-        let mut digits = 1;
-        while let Some(c) = self.peek_char() {
-            //dbg!(c);
-            if !kind.is_valid_digit(c) {
-                break;
-            }
-            number.push(c);
-            digits += 1;
-            self.index += 1;
+        let c = match self.take_literal_char()? {
+            LiteralChar::Char(c) => c,
+            LiteralChar::Byte(b) => b as char,
+        };
+        match self.take_char() {
+            Some('\'') => Ok(TokenKind::Char(c)),
+            Some(_) => Err("Character literals must contain exactly one character".into()),
+            None => Err("Unterminated character literal".into()),
         }
+    }
 
-        if digits == 0 {
-            None
-        } else {
-            self.index += 1;
-            Some((kind.parse(&number)?, kind))
+    /// Consume a string literal: `"abc"`
+    fn take_str_literal(&mut self) -> Result<TokenKind, String> {
+        self.take_char(); // the opening quote
+        let mut bytes = Vec::new();
+        loop {
+            match self.current_char() {
+                Some('"') => {
+                    self.take_char();
+                    return Ok(TokenKind::Str(bytes));
+                }
+                Some(_) => match self.take_literal_char()? {
+                    LiteralChar::Char(c) => {
+                        bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes())
+                    }
+                    LiteralChar::Byte(b) => bytes.push(b),
+                },
+                None => return Err("Unterminated string literal".into()),
+            }
         }
     }
 
-    /// Consume one complete number
-    fn consume_number(&mut self) -> Option<Token> {
+    /// Consume one complete literal using `take`, which is invalid if it is malformed
+    fn consume(&mut self, take: fn(&mut Self) -> Result<TokenKind, String>) -> Token {
         let start = self.index;
-        let num = self.take_num();
-
-        num.map(|(n, kind)| {
-            Token::new(TokenKind::Number(n, kind), TextSpan::new(start, self.index))
-        })
+        let kind = take(self).unwrap_or_else(TokenKind::Invalid);
+        Token::new(kind, TextSpan::new(start, self.index))
     }
 
     /// Get the character that the lexer is current at
@@ -312,33 +481,40 @@ impl Lexer {
             None
         }
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    #[test]
-    pub fn test_take_number() {
-        let mut lex = Lexer::new("123");
-        let (n, kind) = lex.take_num().unwrap();
-
-        assert_eq!((n, kind), (123.0, NumberKind::Dec));
-
-        let mut lex = Lexer::new("123.5123");
-        let (n, kind) = lex.take_num().unwrap();
-
-        assert_eq!((n, kind), (123.5123, NumberKind::Dec));
-
-        let mut lex = Lexer::new("0x123");
-        let (n, kind) = lex.take_num().unwrap();
-
-        assert_eq!((n, kind), (0x123 as f64, NumberKind::Hex));
-
-        let mut lex = Lexer::new("0b101");
-        let (n, kind) = lex.take_num().unwrap();
+    /// Skip the rest of the line, leaving the newline to be lexed
+    fn skip_line(&mut self) {
+        let start = self.index;
+        while !matches!(self.current_char(), None | Some('\n')) {
+            self.index += 1;
+        }
+        self.comments.push(Comment {
+            span: TextSpan::new(start, self.index),
+            line: true,
+            unterminated: false,
+        });
+    }
 
-        assert_eq!((n, kind), (0b101 as f64, NumberKind::Bin));
+    /// Skip a `/* block comment */`, returning false and skipping the rest of the input if it
+    /// never ends
+    fn skip_block_comment(&mut self) -> bool {
+        let start = self.index;
+        self.index += 2;
+        let mut terminated = false;
+        while self.index < self.input.len() {
+            if self.input[self.index] == '*' && self.input.get(self.index + 1) == Some(&'/') {
+                self.index += 2;
+                terminated = true;
+                break;
+            }
+            self.index += 1;
+        }
+        self.comments.push(Comment {
+            span: TextSpan::new(start, self.index),
+            line: false,
+            unterminated: !terminated,
+        });
+        terminated
     }
 }
 
@@ -347,10 +523,11 @@ impl Iterator for Lexer {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index == self.input.len() {
+            // the end of the input is an empty span just after the last character
             self.index += 1;
             return Some(Token::new(
                 TokenKind::Eof,
-                TextSpan::new(self.index - 1, self.index),
+                TextSpan::new(self.input.len(), self.input.len()),
             ));
         }
 
@@ -363,18 +540,22 @@ impl Iterator for Lexer {
             let (tok, kind) = match self.current_char() {
                 Some('+') => (None, Some(TokenKind::Plus)),
                 Some('-') => {
-                    if matches!(self.peek_char(), Some('.' | '0'..='9')) {
+                    if !self.after_operand && matches!(self.peek_char(), Some('.' | '0'..='9')) {
                         self.take_char();
-                        if let Some(mut tok) = self.consume_number() {
+                        let mut tok = self.consume(Self::take_num);
+                        if self.exponent_follows() {
+                            // `-2 ** 2` is `-(2 ** 2)`, like `-x ** 2`, so the sign is left as a
+                            // negation instead of being part of the number
+                            self.index = prev_index;
+                            (None, Some(TokenKind::Minus))
+                        } else {
+                            tok.span = TextSpan::new(prev_index, tok.span.end);
                             tok.kind = match tok.kind {
                                 TokenKind::Number(n, t) => TokenKind::Number(-n, t),
-                                _ => unreachable!(
-                                    "Self::consume_number should always return a number"
-                                ),
+                                TokenKind::Integer(n, t) => TokenKind::Integer(-n, t),
+                                kind => kind,
                             };
                             (Some(tok), None)
-                        } else {
-                            (None, Some(dbg!(TokenKind::Invalid)))
                         }
                     } else {
                         (None, Some(TokenKind::Minus))
@@ -388,17 +569,35 @@ impl Iterator for Lexer {
                         (None, Some(TokenKind::Asterisk))
                     }
                 }
+                // `# comment` and `// comment` run until the end of the line
+                Some('#') => {
+                    self.skip_line();
+                    continue;
+                }
+                Some('/') if self.peek_char() == Some('/') => {
+                    self.skip_line();
+                    continue;
+                }
+                Some('/') if self.peek_char() == Some('*') => {
+                    if self.skip_block_comment() {
+                        continue;
+                    }
+                    let span = TextSpan::new(prev_index, self.index);
+                    let reason = "Unterminated block comment, expected '*/'".into();
+                    (Some(Token::new(TokenKind::Invalid(reason), span)), None)
+                }
                 Some('/') => (None, Some(TokenKind::Slash)),
                 Some('%') => (None, Some(TokenKind::Percent)),
                 //Some('~') => (None, Some(TokenKind::Tilda)),
-                //Some('!') => (None, Some(TokenKind::Bang)),
+                Some('!') => (None, Some(TokenKind::Bang)),
                 Some('^') => (None, Some(TokenKind::Carrot)),
                 Some('&') => (None, Some(TokenKind::Ampersand)),
                 Some('|') => (None, Some(TokenKind::Pipe)),
 
                 Some(',') => (None, Some(TokenKind::Comma)),
+                Some(';') => (None, Some(TokenKind::Semicolon)),
 
-                //Some('=') => (None, Some(TokenKind::Assign)),
+                Some('=') => (None, Some(TokenKind::Equals)),
                 Some('(') => (None, Some(TokenKind::LeftParen)),
                 Some(')') => (None, Some(TokenKind::RightParen)),
                 Some('{') => (None, Some(TokenKind::LeftCurlyBracket)),
@@ -408,37 +607,241 @@ impl Iterator for Lexer {
 
                 Some('\n') => (None, Some(TokenKind::Newline)),
 
+                Some('.') if self.peek_char() == Some('.') => {
+                    self.take_char();
+                    (None, Some(TokenKind::DotDot))
+                }
+
                 Some(c) if c.is_whitespace() => {
                     self.take_char();
                     continue;
                 }
 
-                Some('0'..='9' | '.') => {
-                    if let Some(tok) = self.consume_number() {
-                        (Some(tok), None)
-                    } else {
-                        (None, Some(dbg!(TokenKind::Invalid)))
-                    }
-                }
+                Some('0'..='9' | '.') => (Some(self.consume(Self::take_num)), None),
+                Some('\'') => (Some(self.consume(Self::take_char_literal)), None),
+                Some('"') => (Some(self.consume(Self::take_str_literal)), None),
                 Some('_' | 'a'..='z' | 'A'..='Z') => {
                     if let Some(n) = self.consume_ident() {
                         (Some(n), None)
                     } else {
-                        (None, Some(dbg!(TokenKind::Invalid)))
+                        (
+                            None,
+                            Some(TokenKind::Invalid("Expected an identifier".into())),
+                        )
                     }
                 }
-                Some(_) => (None, Some(TokenKind::Invalid)),
-                None => (None, Some(TokenKind::Eof)),
+                Some(c) => (
+                    None,
+                    Some(TokenKind::Invalid(format!("Unexpected '{}'", c))),
+                ),
+                None => {
+                    self.index += 1;
+                    let end = self.input.len();
+                    (
+                        Some(Token::new(TokenKind::Eof, TextSpan::new(end, end))),
+                        None,
+                    )
+                }
             };
 
-            break (if let Some(tok) = tok {
+            break if let Some(tok) = tok {
                 tok
             } else {
                 self.index += 1;
                 Token::new(kind.unwrap(), TextSpan::new(prev_index, self.index))
-            });
+            };
         };
 
+        self.after_operand = matches!(
+            tok.kind,
+            TokenKind::Number(..)
+                | TokenKind::Integer(..)
+                | TokenKind::Char(_)
+                | TokenKind::Str(_)
+                | TokenKind::Ident(_)
+                | TokenKind::RightParen
+                | TokenKind::RightSquareBrace
+                | TokenKind::RightCurlyBracket
+                | TokenKind::Bang
+        );
+
         Some(tok)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn number(s: &str) -> TokenKind {
+        Lexer::new(s).take_num().unwrap()
+    }
+
+    #[test]
+    pub fn test_take_number() {
+        assert_eq!(number("123"), TokenKind::Number(123.0, NumberKind::Dec));
+        assert_eq!(
+            number("123.5123"),
+            TokenKind::Number(123.5123, NumberKind::Dec)
+        );
+        assert_eq!(number(".5"), TokenKind::Number(0.5, NumberKind::Dec));
+        assert_eq!(number("5."), TokenKind::Number(5.0, NumberKind::Dec));
+        assert_eq!(
+            number("0x123"),
+            TokenKind::Number(0x123 as f64, NumberKind::Hex)
+        );
+        assert_eq!(
+            number("0o755"),
+            TokenKind::Number(0o755 as f64, NumberKind::Oct)
+        );
+        assert_eq!(
+            number("0b101"),
+            TokenKind::Number(0b101 as f64, NumberKind::Bin)
+        );
+        assert_eq!(number("0x1.8p3"), TokenKind::Number(12.0, NumberKind::Hex));
+        assert_eq!(
+            number("0x1.08P-1"),
+            TokenKind::Number(0.515625, NumberKind::Hex)
+        );
+        assert_eq!(
+            number("0xffffffffffffffff"),
+            TokenKind::Integer(u64::MAX as i128, NumberKind::Hex)
+        );
+
+        let mut lex = Lexer::new("12..5");
+        assert_eq!(
+            lex.take_num().unwrap(),
+            TokenKind::Number(12.0, NumberKind::Dec)
+        );
+        assert!(lex.range_at(0));
+    }
+
+    #[test]
+    pub fn test_literal_grammar() {
+        assert_eq!(number("1e-9"), TokenKind::Number(1e-9, NumberKind::Dec));
+        assert_eq!(
+            number("6.022E23"),
+            TokenKind::Number(6.022e23, NumberKind::Dec)
+        );
+        assert_eq!(number("1e+3"), TokenKind::Number(1000.0, NumberKind::Dec));
+        assert_eq!(number("1_000_000"), TokenKind::Number(1e6, NumberKind::Dec));
+        assert_eq!(
+            number("0XDEAD_BEEF"),
+            TokenKind::Number(0xdeadbeef_u32 as f64, NumberKind::Hex)
+        );
+        assert_eq!(
+            number("0B1010_0101"),
+            TokenKind::Number(0b1010_0101 as f64, NumberKind::Bin)
+        );
+        assert_eq!(number("0x1e"), TokenKind::Number(30.0, NumberKind::Hex));
+        assert_eq!(number("0x_ff"), TokenKind::Number(255.0, NumberKind::Hex));
+
+        for (invalid, reason) in [
+            ("1.2.3", "Found more than one '.' in number '1.2.3'"),
+            ("0xZZ", "Invalid digit 'Z' for base 16 in number '0xZZ'"),
+            ("0o8", "Invalid digit '8' for base 8 in number '0o8'"),
+            ("0x", "Expected digits in number '0x'"),
+            ("1e", "Expected digits in the exponent in number '1e'"),
+            ("12ab", "Invalid digit 'a' for base 10 in number '12ab'"),
+            ("1_", "Expected a digit after '_' in number '1_'"),
+            ("1__0", "Found repeated '_' in number '1__0'"),
+            ("1e1_", "Expected a digit after '_' in number '1e1_'"),
+        ] {
+            assert_eq!(
+                Lexer::new(invalid).take_num(),
+                Err(reason.to_string()),
+                "lexing {}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_quoted() {
+        let kind = |s| Lexer::new(s).next().unwrap().kind;
+        assert_eq!(kind("'a'"), TokenKind::Char('a'));
+        assert_eq!(kind("'\\n'"), TokenKind::Char('\n'));
+        assert_eq!(kind("'\\''"), TokenKind::Char('\''));
+        assert_eq!(kind("'\\x41'"), TokenKind::Char('A'));
+        assert_eq!(kind("'\\u{1F600}'"), TokenKind::Char('\u{1F600}'));
+        assert_eq!(kind("\"hi\\n\""), TokenKind::Str(b"hi\n".to_vec()));
+        assert_eq!(kind("\"é\\xff\""), TokenKind::Str(b"\xc3\xa9\xff".to_vec()));
+
+        for invalid in ["''", "'ab'", "'a", "\"abc", "'\\q'", "'\\u{110000}'"] {
+            assert!(
+                matches!(kind(invalid), TokenKind::Invalid(_)),
+                "lexing {}",
+                invalid
+            );
+        }
+
+        assert_eq!(quote_bytes(b"hi\n"), "\"hi\\n\"");
+        assert_eq!(quote_bytes(b"\xff"), "\"\\xff\"");
+    }
+
+    #[test]
+    pub fn test_comments() {
+        let kinds = |s| Lexer::new(s).map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds("1 /* one */ / 2; # two\n3 // three"),
+            vec![
+                TokenKind::Number(1.0, NumberKind::Dec),
+                TokenKind::Slash,
+                TokenKind::Number(2.0, NumberKind::Dec),
+                TokenKind::Semicolon,
+                TokenKind::Newline,
+                TokenKind::Number(3.0, NumberKind::Dec),
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(
+            kinds("1 /* 2"),
+            vec![
+                TokenKind::Number(1.0, NumberKind::Dec),
+                TokenKind::Invalid("Unterminated block comment, expected '*/'".into()),
+                TokenKind::Eof,
+            ]
+        );
+
+        let spans = |s| {
+            comments(s)
+                .into_iter()
+                .map(|c| (c.span.start()..c.span.end(), c.line, c.unterminated))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            spans("1 /* a */ + '#' # b\n\"// c\" /* d"),
+            vec![
+                (2..9, false, false),
+                (16..19, true, false),
+                (27..31, false, true)
+            ]
+        );
+        assert!(ends_in_comment("1 /* one"));
+        assert!(!ends_in_comment("1 /* one */"));
+        assert!(!ends_in_comment("\"/*\""));
+    }
+
+    #[test]
+    pub fn test_minus() {
+        let kinds = |s| Lexer::new(s).map(|t| t.kind).collect::<Vec<_>>();
+        assert_eq!(
+            kinds("1-2"),
+            vec![
+                TokenKind::Number(1.0, NumberKind::Dec),
+                TokenKind::Minus,
+                TokenKind::Number(2.0, NumberKind::Dec),
+                TokenKind::Eof,
+            ]
+        );
+        assert_eq!(
+            kinds("2*-3"),
+            vec![
+                TokenKind::Number(2.0, NumberKind::Dec),
+                TokenKind::Asterisk,
+                TokenKind::Number(-3.0, NumberKind::Dec),
+                TokenKind::Eof,
+            ]
+        );
+    }
+}
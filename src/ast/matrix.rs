@@ -0,0 +1,349 @@
+use std::fmt::Display;
+
+use anyhow::{bail, ensure};
+
+/// Pivots smaller than this (relative to the largest value in the matrix) are treated as zero
+const SINGULAR_EPSILON: f64 = 1e-12;
+/// The most numbers a matrix created from a given size can hold, so that a typo can't use up all
+/// of the memory
+const MAX_ELEMENTS: usize = 1_000_000;
+
+/// A 2-D matrix of numbers, stored in row-major order
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    rows: usize,
+    cols: usize,
+    data: Vec<f64>,
+}
+
+impl Matrix {
+    /// Create a new matrix from its row-major data
+    ///
+    /// Note: `data.len()` _must_ be `rows * cols`
+    pub fn new(rows: usize, cols: usize, data: Vec<f64>) -> Self {
+        assert_eq!(
+            data.len(),
+            rows * cols,
+            "matrix data does not match its size"
+        );
+        Self { rows, cols, data }
+    }
+
+    /// Create a `rows` by `cols` matrix filled with zeros
+    fn zeros(rows: usize, cols: usize) -> anyhow::Result<Self> {
+        let len = rows.checked_mul(cols).filter(|len| *len <= MAX_ELEMENTS);
+        let Some(len) = len else {
+            bail!(
+                "Matrix of {}x{} is too large, the maximum is {} numbers",
+                rows,
+                cols,
+                MAX_ELEMENTS
+            );
+        };
+        Ok(Self::new(rows, cols, vec![0.0; len]))
+    }
+
+    /// Create the `n` by `n` identity matrix
+    pub fn identity(n: usize) -> anyhow::Result<Self> {
+        let mut m = Self::zeros(n, n)?;
+        for i in 0..n {
+            m.set(i, i, 1.0);
+        }
+        Ok(m)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn get(&self, row: usize, col: usize) -> f64 {
+        self.data[row * self.cols + col]
+    }
+
+    fn set(&mut self, row: usize, col: usize, n: f64) {
+        self.data[row * self.cols + col] = n;
+    }
+
+    /// Get a copy of a single row of the matrix
+    pub fn row(&self, row: usize) -> Vec<f64> {
+        self.data[row * self.cols..(row + 1) * self.cols].to_vec()
+    }
+
+    /// Get all numbers in the matrix in row-major order
+    pub fn data(&self) -> &[f64] {
+        &self.data
+    }
+
+    pub fn is_square(&self) -> bool {
+        self.rows == self.cols
+    }
+
+    /// Apply `f` to every number in the matrix
+    pub fn map<F>(&self, f: F) -> Matrix
+    where
+        F: Fn(f64) -> f64,
+    {
+        Self::new(
+            self.rows,
+            self.cols,
+            self.data.iter().map(|n| f(*n)).collect(),
+        )
+    }
+
+    /// Combine two matrices of the same size element-wise
+    pub fn zip_with<F>(&self, other: &Matrix, f: F) -> anyhow::Result<Matrix>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        ensure!(
+            self.rows == other.rows && self.cols == other.cols,
+            "Expected matrices of the same size, found {}x{} and {}x{}",
+            self.rows,
+            self.cols,
+            other.rows,
+            other.cols
+        );
+        Ok(Self::new(
+            self.rows,
+            self.cols,
+            self.data
+                .iter()
+                .zip(&other.data)
+                .map(|(a, b)| f(*a, *b))
+                .collect(),
+        ))
+    }
+
+    pub fn transpose(&self) -> Matrix {
+        let mut out = Self::new(self.cols, self.rows, vec![0.0; self.data.len()]);
+        for r in 0..self.rows {
+            for c in 0..self.cols {
+                out.set(c, r, self.get(r, c));
+            }
+        }
+        out
+    }
+
+    /// Matrix multiplication: `self * other`
+    pub fn mul(&self, other: &Matrix) -> anyhow::Result<Matrix> {
+        ensure!(
+            self.cols == other.rows,
+            "Cannot multiply a {}x{} matrix by a {}x{} matrix",
+            self.rows,
+            self.cols,
+            other.rows,
+            other.cols
+        );
+
+        let mut out = Self::new(self.rows, other.cols, vec![0.0; self.rows * other.cols]);
+        for r in 0..self.rows {
+            for c in 0..other.cols {
+                let n = (0..self.cols)
+                    .map(|i| self.get(r, i) * other.get(i, c))
+                    .sum();
+                out.set(r, c, n);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Multiply this matrix by a column vector
+    pub fn mul_vec(&self, v: &[f64]) -> anyhow::Result<Vec<f64>> {
+        Ok(self.mul(&Self::new(v.len(), 1, v.to_vec()))?.data)
+    }
+
+    /// Raise this matrix to an integer power, using the inverse for negative powers
+    pub fn pow(&self, exp: i64) -> anyhow::Result<Matrix> {
+        ensure!(
+            self.is_square(),
+            "Only square matrices can be raised to a power"
+        );
+
+        let mut base = if exp < 0 {
+            self.inverse()?
+        } else {
+            self.clone()
+        };
+        let mut exp = exp.unsigned_abs();
+        let mut out = Self::identity(self.rows)?;
+        while exp > 0 {
+            if exp & 1 == 1 {
+                out = out.mul(&base)?;
+            }
+            base = base.mul(&base)?;
+            exp >>= 1;
+        }
+        Ok(out)
+    }
+
+    /// The threshold below which a pivot is considered to be zero
+    fn epsilon(&self) -> f64 {
+        self.data.iter().fold(0.0, |m: f64, n| m.max(n.abs())) * SINGULAR_EPSILON
+    }
+
+    /// Find the determinant using gaussian elimination with partial pivoting
+    pub fn det(&self) -> anyhow::Result<f64> {
+        ensure!(
+            self.is_square(),
+            "Determinant requires a square matrix, found {}x{}",
+            self.rows,
+            self.cols
+        );
+
+        let eps = self.epsilon();
+        let mut m = self.clone();
+        let mut det = 1.0;
+        for col in 0..m.cols {
+            let pivot = m.pivot_row(col, col);
+            if m.get(pivot, col).abs() <= eps {
+                return Ok(0.0);
+            }
+            if pivot != col {
+                m.swap_rows(pivot, col);
+                det = -det;
+            }
+
+            let p = m.get(col, col);
+            det *= p;
+            for r in col + 1..m.rows {
+                let factor = m.get(r, col) / p;
+                for c in col..m.cols {
+                    m.set(r, c, m.get(r, c) - factor * m.get(col, c));
+                }
+            }
+        }
+        Ok(det)
+    }
+
+    /// Find the inverse using gauss-jordan elimination
+    pub fn inverse(&self) -> anyhow::Result<Matrix> {
+        ensure!(
+            self.is_square(),
+            "Inverse requires a square matrix, found {}x{}",
+            self.rows,
+            self.cols
+        );
+
+        self.eliminate(Self::identity(self.rows)?)
+    }
+
+    /// Solve `self * x = b` for `x`
+    pub fn solve(&self, b: &[f64]) -> anyhow::Result<Vec<f64>> {
+        ensure!(
+            self.is_square(),
+            "Solving requires a square matrix, found {}x{}",
+            self.rows,
+            self.cols
+        );
+        ensure!(
+            b.len() == self.rows,
+            "Expected a vector of length {}, found {}",
+            self.rows,
+            b.len()
+        );
+
+        Ok(self.eliminate(Self::new(b.len(), 1, b.to_vec()))?.data)
+    }
+
+    /// Reduce `self` to the identity using gauss-jordan elimination, applying the same row
+    /// operations to `rhs`, which is then returned.
+    fn eliminate(&self, mut rhs: Matrix) -> anyhow::Result<Matrix> {
+        let eps = self.epsilon();
+        let mut m = self.clone();
+        for col in 0..m.cols {
+            let pivot = m.pivot_row(col, col);
+            if m.get(pivot, col).abs() <= eps {
+                bail!("Matrix is singular");
+            }
+            m.swap_rows(pivot, col);
+            rhs.swap_rows(pivot, col);
+
+            let p = m.get(col, col);
+            m.scale_row(col, 1.0 / p);
+            rhs.scale_row(col, 1.0 / p);
+
+            for r in 0..m.rows {
+                if r == col {
+                    continue;
+                }
+                let factor = m.get(r, col);
+                m.sub_row(r, col, factor);
+                rhs.sub_row(r, col, factor);
+            }
+        }
+        Ok(rhs)
+    }
+
+    /// Find the row at or below `start` with the largest absolute value in `col`
+    fn pivot_row(&self, start: usize, col: usize) -> usize {
+        (start..self.rows)
+            .max_by(|a, b| self.get(*a, col).abs().total_cmp(&self.get(*b, col).abs()))
+            .unwrap_or(start)
+    }
+
+    fn swap_rows(&mut self, a: usize, b: usize) {
+        for c in 0..self.cols {
+            self.data.swap(a * self.cols + c, b * self.cols + c);
+        }
+    }
+
+    fn scale_row(&mut self, row: usize, factor: f64) {
+        for c in 0..self.cols {
+            self.set(row, c, self.get(row, c) * factor);
+        }
+    }
+
+    /// `rows[dest] -= rows[src] * factor`
+    fn sub_row(&mut self, dest: usize, src: usize, factor: f64) {
+        for c in 0..self.cols {
+            self.set(dest, c, self.get(dest, c) - self.get(src, c) * factor);
+        }
+    }
+}
+
+impl Display for Matrix {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[")?;
+        for r in 0..self.rows {
+            write!(f, "[")?;
+            for c in 0..self.cols {
+                write!(f, "{}", self.get(r, c))?;
+                if c != self.cols - 1 {
+                    write!(f, ", ")?;
+                }
+            }
+            write!(f, "]")?;
+            if r != self.rows - 1 {
+                write!(f, ", ")?;
+            }
+        }
+        write!(f, "]")
+    }
+}
+
+/// Find the dot product of two vectors
+pub fn dot(a: &[f64], b: &[f64]) -> anyhow::Result<f64> {
+    ensure!(
+        a.len() == b.len(),
+        "Expected vectors of the same length, found {} and {}",
+        a.len(),
+        b.len()
+    );
+    Ok(a.iter().zip(b).map(|(a, b)| a * b).sum())
+}
+
+/// Find the cross product of two 3-D vectors
+pub fn cross(a: &[f64], b: &[f64]) -> anyhow::Result<Vec<f64>> {
+    let (&[a1, a2, a3], &[b1, b2, b3]) = (a, b) else {
+        bail!(
+            "Expected two vectors of length 3, found {} and {}",
+            a.len(),
+            b.len()
+        );
+    };
+    Ok(vec![
+        a2 * b3 - a3 * b2,
+        a3 * b1 - a1 * b3,
+        a1 * b2 - a2 * b1,
+    ])
+}
@@ -1,13 +1,28 @@
-use std::{collections::HashMap, fmt::Display, str::FromStr};
+use std::{cell::RefCell, collections::HashMap, fmt::Display, str::FromStr};
 
 use anyhow::{bail, ensure, Context};
 
+pub mod bits;
+pub mod calculus;
+pub mod checksum;
+pub mod float;
 pub mod functions;
+pub mod integer;
 pub mod lexer;
+pub mod matrix;
 pub mod op;
-
-use lexer::{Lexer, Token, TokenKind};
+pub mod polynomial;
+pub mod rpn;
+pub mod stats;
+pub mod symbolic;
+pub mod trace;
+pub mod value;
+
+use lexer::{Lexer, NumberKind, Token, TokenKind};
 use op::Operator;
+use symbolic::Expr;
+use trace::Trace;
+use value::Value;
 
 #[cfg(test)]
 mod test;
@@ -16,6 +31,12 @@ mod test;
 pub enum AstStatement {
     /// A number
     Number(f64),
+    /// An integer which is too large to be stored exactly in an f64
+    Integer(i128),
+    /// A character: `'a'`
+    Char(char),
+    /// A string, which is a list of its bytes: `"abc"`
+    Str(Vec<u8>),
     /// A variable reference
     Variable(String),
     /// A maths expression using Infix Notation (a + b) -- for evaluation, this gets convert to
@@ -29,17 +50,45 @@ pub enum AstStatement {
         name: String,
         params: Vec<AstStatement>,
     },
+    /// A list literal: `[1, 2, 3]`
+    List(Vec<AstStatement>),
+    /// Indexing into a list: `xs[0]`
+    Index {
+        target: Box<AstStatement>,
+        index: Box<AstStatement>,
+    },
+    /// A block with its own variables, which is the value of its last statement:
+    /// `{ let a = 2; let b = a * 3; a + b }`
+    Block {
+        bindings: Vec<(String, AstStatement)>,
+        result: Box<AstStatement>,
+    },
+    /// A definition of a function, which can only be a whole statement: `let f(x) = x ** 2`
+    Define {
+        name: String,
+        params: Vec<String>,
+        body: Box<AstStatement>,
+    },
 }
 
 impl Display for AstStatement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             AstStatement::Number(n) => write!(f, "{}", n),
+            AstStatement::Integer(n) => write!(f, "{}", n),
+            AstStatement::Char(c) => write!(f, "{:?}", c),
+            AstStatement::Str(bytes) => write!(f, "{}", lexer::quote_bytes(bytes)),
             AstStatement::Variable(v) => write!(f, "{}", v),
             AstStatement::InfixExpression(expr) => {
+                // show it as a tree with as few parentheses as possible, if it's valid
+                if let Ok(tree) = Expr::try_from(self) {
+                    return write!(f, "{}", tree);
+                }
+
                 for (i, e) in expr.iter().enumerate() {
                     write!(f, "{}", e)?;
-                    if i != expr.len() - 1 {
+                    let unary = matches!(e, AstStatement::Operator(o) if o.is_unary());
+                    if i != expr.len() - 1 && !unary {
                         write!(f, " ")?;
                     }
                 }
@@ -56,6 +105,27 @@ impl Display for AstStatement {
                 }
                 write!(f, ")")
             }
+            AstStatement::List(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    write!(f, "{}", item)?;
+                    if i != items.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, "]")
+            }
+            AstStatement::Index { target, index } => write!(f, "{}[{}]", target, index),
+            AstStatement::Block { bindings, result } => {
+                write!(f, "{{ ")?;
+                for (name, value) in bindings {
+                    write!(f, "let {} = {}; ", name, value)?;
+                }
+                write!(f, "{} }}", result)
+            }
+            AstStatement::Define { name, params, body } => {
+                write!(f, "let {}({}) = {}", name, params.join(", "), body)
+            }
         }
     }
 }
@@ -64,10 +134,35 @@ impl AstStatement {
     pub fn is_operand(&self) -> bool {
         match self {
             AstStatement::Number(_) => true,
+            AstStatement::Integer(_) => true,
+            AstStatement::Char(_) => true,
+            AstStatement::Str(_) => true,
             AstStatement::Variable(_) => true,
             AstStatement::InfixExpression(_) => false,
             AstStatement::Operator(_) => false,
             AstStatement::FunctionCall { .. } => true,
+            AstStatement::List(_) => true,
+            AstStatement::Index { .. } => true,
+            AstStatement::Block { .. } => true,
+            AstStatement::Define { .. } => false,
+        }
+    }
+
+    /// Whether this statement calls the function `name` anywhere inside it
+    pub fn calls(&self, name: &str) -> bool {
+        match self {
+            AstStatement::FunctionCall { name: n, params } => {
+                n == name || params.iter().any(|p| p.calls(name))
+            }
+            AstStatement::InfixExpression(items) | AstStatement::List(items) => {
+                items.iter().any(|i| i.calls(name))
+            }
+            AstStatement::Index { target, index } => target.calls(name) || index.calls(name),
+            AstStatement::Block { bindings, result } => {
+                bindings.iter().any(|(_, v)| v.calls(name)) || result.calls(name)
+            }
+            AstStatement::Define { body, .. } => body.calls(name),
+            _ => false,
         }
     }
 
@@ -83,46 +178,295 @@ impl AstStatement {
         tokens: &[Token],
         i: &mut usize,
     ) -> anyhow::Result<AstStatement> {
-        let mut args = Vec::new(); // All args for this function
+        Ok(AstStatement::FunctionCall {
+            name: ident,
+            params: Self::consume_args(tokens, i, TokenKind::RightParen)?,
+        })
+    }
+
+    /// Consume a comma-separated list of expressions, ending at `close` (which is consumed)
+    fn consume_args(
+        tokens: &[Token],
+        i: &mut usize,
+        close: TokenKind,
+    ) -> anyhow::Result<Vec<AstStatement>> {
+        let mut args = Vec::new(); // All args
         let mut curr_arg = Vec::new(); // the tokens in the current arg
-        let mut depth = 0; // Paren depth -- 0 for same level as function
+        let mut depth = 0; // Paren/bracket depth -- 0 for same level as the args
         while let Some(tok) = tokens.get(*i) {
             match tok.kind {
-                TokenKind::RightParen if depth == 0 => {
-                    args.push(Self::infix_expr_from_tokens(&curr_arg)?);
-                    return Ok(AstStatement::FunctionCall {
-                        name: ident,
-                        params: args,
-                    });
+                ref k if *k == close && depth == 0 => {
+                    // Allow an empty list of args: `f()` or `[]`
+                    if !args.is_empty() || !curr_arg.is_empty() {
+                        args.push(Self::infix_expr_from_tokens(&curr_arg)?);
+                    }
+                    return Ok(args);
                 }
-                TokenKind::RightParen => {
+                TokenKind::RightParen
+                | TokenKind::RightSquareBrace
+                | TokenKind::RightCurlyBracket => {
                     depth -= 1;
                     curr_arg.push(tok.clone());
                 }
-                TokenKind::LeftParen => {
+                TokenKind::LeftParen
+                | TokenKind::LeftSquareBracket
+                | TokenKind::LeftCurlyBracket => {
                     depth += 1;
                     curr_arg.push(tok.clone());
                 }
-                TokenKind::Comma => {
+                TokenKind::Comma if depth == 0 => {
                     args.push(Self::infix_expr_from_tokens(&curr_arg)?);
                     curr_arg.clear();
                 }
-                TokenKind::Eof => bail!("Expected RightParen, found EOF"),
+                TokenKind::Eof => bail!("Expected {:?}, found EOF", close),
 
                 _ => curr_arg.push(tok.clone()),
             }
             *i += 1;
         }
-        todo!()
+        bail!("Expected {:?}, found end of input", close)
+    }
+
+    /// Consume a block, `{ let a = 2; a + 1 }`, ending at its closing brace (which is consumed)
+    fn consume_block(tokens: &[Token], i: &mut usize) -> anyhow::Result<AstStatement> {
+        let start = *i;
+        let mut depth = 0;
+        loop {
+            match tokens.get(*i).map(|t| &t.kind) {
+                Some(TokenKind::RightCurlyBracket) if depth == 0 => break,
+                Some(
+                    TokenKind::RightParen
+                    | TokenKind::RightSquareBrace
+                    | TokenKind::RightCurlyBracket,
+                ) => depth -= 1,
+                Some(
+                    TokenKind::LeftParen
+                    | TokenKind::LeftSquareBracket
+                    | TokenKind::LeftCurlyBracket,
+                ) => depth += 1,
+                Some(TokenKind::Eof) | None => bail!("Expected '}}', found end of input"),
+                _ => {}
+            }
+            *i += 1;
+        }
+
+        let mut stmts = Self::split_statements(&tokens[start..*i]);
+        let last = stmts.pop().context("Expected a statement in the block")?;
+        let bindings = stmts
+            .into_iter()
+            .map(|stmt| match stmt {
+                [Token {
+                    kind: TokenKind::Let,
+                    ..
+                }, Token {
+                    kind: TokenKind::Ident(name),
+                    ..
+                }, Token {
+                    kind: TokenKind::Equals,
+                    ..
+                }, value @ ..] => Ok((
+                    name.clone(),
+                    Self::infix_expr_from_tokens(value)
+                        .with_context(|| format!("parsing variable: {}", name))?,
+                )),
+                [Token {
+                    kind: TokenKind::Let,
+                    ..
+                }, ..] => bail!("Expected a variable like `let a = 2`"),
+                _ => bail!("Expected `let`, only the last statement in a block is its value"),
+            })
+            .collect::<anyhow::Result<_>>()?;
+        if let Some(Token {
+            kind: TokenKind::Let,
+            ..
+        }) = last.first()
+        {
+            bail!("Expected a value at the end of the block, found `let`");
+        }
+
+        Ok(AstStatement::Block {
+            bindings,
+            result: Box::new(Self::infix_expr_from_tokens(last)?),
+        })
+    }
+
+    /// Split `tokens` at each `;` which isn't inside brackets or a block, leaving out any
+    /// statements which are empty
+    fn split_statements(tokens: &[Token]) -> Vec<&[Token]> {
+        let mut out = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (j, tok) in tokens.iter().enumerate() {
+            match tok.kind {
+                TokenKind::LeftParen
+                | TokenKind::LeftSquareBracket
+                | TokenKind::LeftCurlyBracket => depth += 1,
+                TokenKind::RightParen
+                | TokenKind::RightSquareBrace
+                | TokenKind::RightCurlyBracket => depth -= 1,
+                TokenKind::Semicolon if depth == 0 => {
+                    out.push(&tokens[start..j]);
+                    start = j + 1;
+                }
+                _ => {}
+            }
+        }
+        out.push(&tokens[start..]);
+        out.retain(|stmt| {
+            stmt.iter()
+                .any(|t| !matches!(t.kind, TokenKind::Newline | TokenKind::Eof))
+        });
+        out
+    }
+
+    /// Remove the last operand from an infix expression which is being built, including the whole
+    /// group if it ends with a right paren: `(a + b)`
+    fn pop_operand(stmts: &mut Vec<AstStatement>) -> anyhow::Result<AstStatement> {
+        match stmts.last() {
+            Some(s) if s.is_operand() => Ok(stmts.pop().expect("checked above")),
+            Some(AstStatement::Operator(Operator::RightParen)) => {
+                let mut depth = 0;
+                for i in (0..stmts.len()).rev() {
+                    match stmts[i] {
+                        AstStatement::Operator(Operator::RightParen) => depth += 1,
+                        AstStatement::Operator(Operator::LeftParen) => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        // drop the parens, they aren't needed once the group is separate
+                        let mut group: Vec<_> = stmts.drain(i..).collect();
+                        group.pop();
+                        group.remove(0);
+                        return Ok(AstStatement::InfixExpression(group));
+                    }
+                }
+                bail!("Expected LeftParen, found start of expression")
+            }
+            Some(s) => bail!("Expected an operand, found {}", s),
+            None => bail!("Expected an operand, found start of expression"),
+        }
+    }
+
+    /// Parse an expression, reading hex and binary literals as two's complement integers of
+    /// `width` bits, so `0xff` is `-1` when `width` is 8
+    pub fn parse_signed(s: &str, width: u32) -> anyhow::Result<Self> {
+        Self::infix_expr_from_tokens(&Self::signed_tokens(s, width)?)
+    }
+
+    /// Parse every statement in `s`, which are separated by `;`, skipping any which are empty
+    /// (like a line which is just a comment).  Hex and binary literals are read as signed
+    /// integers when `signed_width` is given, like [`Self::parse_signed`].
+    pub fn parse_statements(s: &str, signed_width: Option<u32>) -> anyhow::Result<Vec<Self>> {
+        let tokens = match signed_width {
+            Some(width) => Self::signed_tokens(s, width)?,
+            None => Lexer::new(s).collect(),
+        };
+        Self::split_statements(&tokens)
+            .into_iter()
+            .map(|stmt| {
+                // a continued line can start with line breaks
+                let start = stmt
+                    .iter()
+                    .position(|t| t.kind != TokenKind::Newline)
+                    .unwrap_or(0);
+                match stmt[start].kind {
+                    TokenKind::Let => Self::definition_from_tokens(&stmt[start..]),
+                    _ => Self::infix_expr_from_tokens(stmt),
+                }
+            })
+            .collect()
+    }
+
+    /// Parse the definition of a function, `let f(x, y) = x * y`, which starts at `let`
+    fn definition_from_tokens(tokens: &[Token]) -> anyhow::Result<Self> {
+        let usage = "Expected a function like `let f(x) = x ** 2`, variables can only be set in a \
+                     block: `{ let a = 2; a }`";
+        let [_, Token {
+            kind: TokenKind::Ident(name),
+            ..
+        }, Token {
+            kind: TokenKind::LeftParen,
+            ..
+        }, rest @ ..] = tokens
+        else {
+            bail!(usage);
+        };
+
+        let close = rest
+            .iter()
+            .position(|t| t.kind == TokenKind::RightParen)
+            .context(usage)?;
+        let params = rest[..close]
+            .split(|t| t.kind == TokenKind::Comma)
+            .filter(|_| close > 0)
+            .map(|param| match param {
+                [Token {
+                    kind: TokenKind::Ident(p),
+                    ..
+                }] => Ok(p.clone()),
+                _ => bail!("Expected the name of a parameter in `let {}(...)`", name),
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        for (i, p) in params.iter().enumerate() {
+            ensure!(!params[..i].contains(p), "Repeated parameter: '{}'", p);
+        }
+
+        let Some(Token {
+            kind: TokenKind::Equals,
+            ..
+        }) = rest.get(close + 1)
+        else {
+            bail!(usage);
+        };
+        let body = Self::infix_expr_from_tokens(&rest[close + 2..])
+            .with_context(|| format!("parsing function: {}", name))?;
+        Ok(AstStatement::Define {
+            name: name.clone(),
+            params,
+            body: Box::new(body),
+        })
+    }
+
+    /// Lex `s`, converting hex and binary literals into two's complement integers of `width` bits
+    fn signed_tokens(s: &str, width: u32) -> anyhow::Result<Vec<Token>> {
+        Lexer::new(s)
+            .map(|mut tok| {
+                let (n, kind) = match tok.kind {
+                    TokenKind::Number(n, kind)
+                        if kind != NumberKind::Dec && n >= 0.0 && n.fract() == 0.0 =>
+                    {
+                        (n as u128, kind)
+                    }
+                    TokenKind::Integer(n, kind) if kind != NumberKind::Dec && n >= 0 => {
+                        (n as u128, kind)
+                    }
+                    _ => return Ok(tok),
+                };
+                ensure!(
+                    n <= bits::mask(width),
+                    "{:#x} does not fit in {} bits",
+                    n,
+                    width
+                );
+                tok.kind = match Value::int(bits::sext(n, width)) {
+                    Value::Int(n) => TokenKind::Integer(n, kind),
+                    v => TokenKind::Number(v.as_number()?, kind),
+                };
+                Ok(tok)
+            })
+            .collect()
     }
 
-    fn infix_expr_from_tokens(tokens: &Vec<Token>) -> anyhow::Result<AstStatement> {
-        let mut stmts = Vec::new();
+    fn infix_expr_from_tokens(tokens: &[Token]) -> anyhow::Result<AstStatement> {
+        let mut stmts: Vec<AstStatement> = Vec::new();
         let mut i = 0;
         while i < tokens.len() {
             let tok = &tokens[i];
-            stmts.push(match &tok.kind {
+            let stmt = match &tok.kind {
                 TokenKind::Number(n, _) => AstStatement::Number(*n),
+                TokenKind::Integer(n, _) => AstStatement::Integer(*n),
+                TokenKind::Char(c) => AstStatement::Char(*c),
+                TokenKind::Str(bytes) => AstStatement::Str(bytes.clone()),
                 TokenKind::Ident(ident) => match tokens.get(i + 1).map(|t| &t.kind) {
                     Some(TokenKind::LeftParen) => {
                         i += 2; // skip the ident and the paren
@@ -133,7 +477,12 @@ impl AstStatement {
                 },
 
                 TokenKind::Plus => Operator::Add.into(),
-                TokenKind::Minus => Operator::Subtract.into(),
+                TokenKind::Minus => match stmts.last() {
+                    Some(s) if s.is_operand() => Operator::Subtract.into(),
+                    Some(AstStatement::Operator(Operator::RightParen)) => Operator::Subtract.into(),
+                    // a minus which doesn't follow an operand negates the next one: `-x`
+                    _ => Operator::Negate.into(),
+                },
                 TokenKind::Asterisk => Operator::Multiply.into(),
                 TokenKind::Slash => Operator::Divide.into(),
                 TokenKind::Percent => Operator::Modulo.into(),
@@ -142,24 +491,68 @@ impl AstStatement {
                 TokenKind::Carrot => Operator::Xor.into(),
                 TokenKind::Ampersand => Operator::BitAnd.into(),
                 TokenKind::Pipe => Operator::BitOr.into(),
+                TokenKind::DotDot => Operator::Range.into(),
+                TokenKind::Equals => Operator::Equals.into(),
+                TokenKind::Bang => {
+                    // `n!` is shorthand for `factorial(n)`
+                    let operand = Self::pop_operand(&mut stmts).context("parsing factorial")?;
+                    AstStatement::FunctionCall {
+                        name: "factorial".into(),
+                        params: vec![operand],
+                    }
+                }
 
                 TokenKind::Comma => bail!("Unexpected token: ','"),
+                TokenKind::Semicolon => bail!("Unexpected token: ';'"),
 
                 TokenKind::LeftParen => Operator::LeftParen.into(),
                 TokenKind::RightParen => Operator::RightParen.into(),
 
-                TokenKind::LeftCurlyBracket => bail!("Unexpected token: '{{'"),
+                TokenKind::LeftCurlyBracket => {
+                    i += 1; // skip the brace
+                    Self::consume_block(tokens, &mut i).context("parsing block")?
+                }
                 TokenKind::RightCurlyBracket => bail!("Unexpected token: '}}'"),
-                TokenKind::LeftSquareBracket => bail!("Unexpected token: '['"),
+                TokenKind::LeftSquareBracket => {
+                    i += 1; // skip the bracket
+                    let items = Self::consume_args(tokens, &mut i, TokenKind::RightSquareBrace)
+                        .context("parsing list")?;
+                    match stmts.pop() {
+                        // `xs[0]` -- an operand directly followed by brackets is an index
+                        Some(target) if target.is_operand() => {
+                            ensure!(
+                                items.len() == 1,
+                                "Expected 1 index, found {}: '{}[...]'",
+                                items.len(),
+                                target
+                            );
+                            AstStatement::Index {
+                                target: Box::new(target),
+                                index: Box::new(items.into_iter().next().expect("checked above")),
+                            }
+                        }
+                        prev => {
+                            stmts.extend(prev);
+                            AstStatement::List(items)
+                        }
+                    }
+                }
                 TokenKind::RightSquareBrace => bail!("Unexpected token: ']'"),
 
-                TokenKind::Newline => continue,
+                TokenKind::Newline => {
+                    i += 1;
+                    continue;
+                }
 
-                TokenKind::Let => unimplemented!(),
+                TokenKind::Let => bail!(
+                    "`let` can only be used in a block, `{{ let a = 2; a }}`, or to define a \
+                     function at the start of a statement, `let f(x) = x ** 2`"
+                ),
 
                 TokenKind::Eof => break, //bail!("Expected token, found EOF"),
-                TokenKind::Invalid => bail!("Found invalid token"),
-            });
+                TokenKind::Invalid(reason) => bail!("{}", reason),
+            };
+            stmts.push(stmt);
             i += 1;
         }
         Ok(AstStatement::InfixExpression(stmts))
@@ -171,7 +564,7 @@ impl FromStr for AstStatement {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let lexer = Lexer::new(s);
-        Self::infix_expr_from_tokens(&lexer.collect())
+        Self::infix_expr_from_tokens(&lexer.collect::<Vec<_>>())
     }
 }
 
@@ -181,34 +574,100 @@ impl From<f64> for AstStatement {
     }
 }
 
+/// The constants which every evaluator starts with
+pub const CONSTANTS: &[(&str, f64)] = &[
+    ("pi", std::f64::consts::PI),
+    ("e", std::f64::consts::E),
+    ("inf", f64::INFINITY),
+    ("true", 1.0),
+    ("false", 0.0),
+];
+
+/// The most scopes which can be nested, which stops a function which calls itself from
+/// overflowing the stack
+const MAX_SCOPES: usize = 64;
+
+/// A function defined by a statement like `let f(x, y) = x * y`
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserFunction {
+    pub params: Vec<String>,
+    pub body: AstStatement,
+}
+
 pub struct AstEvaluator {
-    pub variable_map: HashMap<String, f64>,
+    pub variable_map: HashMap<String, Value>,
     pub const_map: HashMap<&'static str, f64>,
     pub known_functions: functions::FnMap,
+    /// Functions which were defined by earlier statements
+    user_functions: RefCell<HashMap<String, UserFunction>>,
+    /// Variables which are only bound while evaluating part of an expression, like the `x` in
+    /// `diff(x ** 2, x, 3)`.  The innermost scope is last.
+    scopes: RefCell<Vec<HashMap<String, Value>>>,
+    /// The steps taken while evaluating, which are only recorded after [`Self::start_trace`]
+    trace: RefCell<Option<Trace>>,
 }
 
 impl AstEvaluator {
     pub fn new() -> Self {
-        let mut const_map = HashMap::new();
-        const_map.insert("pi", std::f64::consts::PI);
-        const_map.insert("e", std::f64::consts::E);
-        const_map.insert("inf", f64::INFINITY);
-        const_map.insert("true", 1.0);
-        const_map.insert("false", 0.0);
-
         Self {
             variable_map: Default::default(),
-            const_map,
+            const_map: CONSTANTS.iter().copied().collect(),
             known_functions: functions::default_functions(),
+            user_functions: Default::default(),
+            scopes: Default::default(),
+            trace: Default::default(),
+        }
+    }
+
+    /// Record the steps taken by every evaluation until [`Self::take_trace`] is called
+    pub fn start_trace(&self) {
+        *self.trace.borrow_mut() = Some(Trace::default());
+    }
+
+    /// Stop recording steps, and get the steps which were recorded
+    pub fn take_trace(&self) -> Option<Trace> {
+        self.trace.borrow_mut().take()
+    }
+
+    fn is_tracing(&self) -> bool {
+        self.trace.borrow().is_some()
+    }
+
+    /// Run `f` as a step in the trace, which is described by `describe` and its result
+    fn traced<D, F>(&self, describe: D, f: F) -> anyhow::Result<Value>
+    where
+        D: FnOnce() -> String,
+        F: FnOnce() -> anyhow::Result<Value>,
+    {
+        let step = self.trace.borrow_mut().as_mut().map(Trace::enter);
+        let Some(step) = step else {
+            return f();
+        };
+
+        let out = f();
+        if let Some(trace) = self.trace.borrow_mut().as_mut() {
+            trace.exit(step, describe, &out);
         }
+        out
     }
 
     /// Evaluate a full [`AstStatement`] into its true value.  This does lookups into the
     /// constants and variables, runs functions, and evaluates operators
-    pub fn eval(&self, statement: &AstStatement) -> anyhow::Result<f64> {
+    pub fn eval(&self, statement: &AstStatement) -> anyhow::Result<Value> {
         Ok(match statement {
-            AstStatement::Number(f) => *f,
-            AstStatement::Variable(ref v) => self.get_variable(v.clone())?,
+            AstStatement::Number(f) => Value::Number(*f),
+            AstStatement::Integer(n) => Value::int(*n),
+            AstStatement::Char(c) => Value::Char(*c),
+            AstStatement::Str(bytes) => {
+                Value::List(bytes.iter().map(|b| (*b as f64).into()).collect())
+            }
+            AstStatement::Variable(ref v) => {
+                self.traced(|| v.clone(), || self.get_variable(v.clone()))?
+            }
+            // an expression which is just one operand would repeat that operand's step
+            AstStatement::InfixExpression(ref x) if x.iter().any(|s| !s.is_operand()) => self
+                .traced(|| statement.to_string(), || self.eval_infix(x))
+                .with_context(|| format!("Evaluating expression: '{}'", statement))?,
             AstStatement::InfixExpression(ref x) => self
                 .eval_infix(x)
                 .with_context(|| format!("Evaluating expression: '{}'", statement))?,
@@ -216,23 +675,162 @@ impl AstEvaluator {
                 ref name,
                 ref params,
             } => self
-                .eval_function(name, params.to_vec())
+                .traced(
+                    || statement.to_string(),
+                    || self.eval_function(name, params.to_vec()),
+                )
                 .with_context(|| format!("Evaluating function: '{}'", statement))?,
+            AstStatement::List(ref items) => Value::list(
+                items
+                    .iter()
+                    .map(|i| self.eval(i))
+                    .collect::<anyhow::Result<_>>()
+                    .with_context(|| format!("Evaluating list: '{}'", statement))?,
+            ),
+            AstStatement::Index {
+                ref target,
+                ref index,
+            } => self
+                .eval(target)?
+                .index(self.eval(index)?.as_integer()?)
+                .with_context(|| format!("Evaluating index: '{}'", statement))?,
+            AstStatement::Block {
+                ref bindings,
+                ref result,
+            } => {
+                self.scopes.borrow_mut().push(HashMap::new());
+                let out = (|| {
+                    for (name, value) in bindings {
+                        let value = self
+                            .eval(value)
+                            .with_context(|| format!("Evaluating variable: '{}'", name))?;
+                        self.scopes
+                            .borrow_mut()
+                            .last_mut()
+                            .expect("pushed above")
+                            .insert(name.clone(), value);
+                    }
+                    self.eval(result)
+                })();
+                self.scopes.borrow_mut().pop();
+                out.with_context(|| format!("Evaluating block: '{}'", statement))?
+            }
+            AstStatement::Define {
+                ref name,
+                ref params,
+                ref body,
+            } => self
+                .define(name, params, body)
+                .with_context(|| format!("Defining function: '{}'", statement))?,
             AstStatement::Operator(o) => bail!("Expected expression, found {:?}", o),
         })
     }
 
-    /// Get the value of a constant or variable (in that order)
-    pub fn get_variable(&self, v: String) -> anyhow::Result<f64> {
-        Ok(*self
-            .const_map
-            .get(v.as_str())
-            .or_else(|| self.variable_map.get(&v))
-            .with_context(|| format!("Undeclared variable or constant: '{}'", v))?)
+    /// Define the function `name`, replacing any earlier definition, and give its body.
+    ///
+    /// A body which works out to an expression without any arguments, like `deriv(x ** 3, x)`,
+    /// is replaced by that expression, so the derivative is only found once.
+    fn define(&self, name: &str, params: &[String], body: &AstStatement) -> anyhow::Result<Value> {
+        ensure!(
+            self.arity(name).is_none() || self.user_functions.borrow().contains_key(name),
+            "Cannot redefine the built-in function '{}'",
+            name
+        );
+        ensure!(!body.calls(name), "A function can't call itself");
+        let body = match self.eval(body) {
+            Ok(Value::Expr(e)) => AstStatement::from(&*e),
+            _ => body.clone(),
+        };
+        let value = Expr::try_from(&body)?.into();
+        self.user_functions.borrow_mut().insert(
+            name.to_string(),
+            UserFunction {
+                params: params.to_vec(),
+                body,
+            },
+        );
+        Ok(value)
+    }
+
+    /// Call a function defined by the user, with its parameters bound to the values of `args`
+    fn call(
+        &self,
+        name: &str,
+        function: &UserFunction,
+        args: &[AstStatement],
+    ) -> anyhow::Result<Value> {
+        ensure!(
+            args.len() == function.params.len(),
+            "Expected {} arg(s): `{}({})`",
+            function.params.len(),
+            name,
+            function.params.join(", ")
+        );
+        ensure!(
+            self.scopes.borrow().len() < MAX_SCOPES,
+            "Too many nested calls, a function can't call itself"
+        );
+        let values = args
+            .iter()
+            .map(|a| self.eval(a))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        self.scopes
+            .borrow_mut()
+            .push(function.params.iter().cloned().zip(values).collect());
+        let out = self.eval(&function.body);
+        self.scopes.borrow_mut().pop();
+        out
+    }
+
+    /// The number of arguments the function `name` takes, or `None` if there's no such function.
+    /// Functions which take a varying number of arguments give the number they most often take.
+    pub fn arity(&self, name: &str) -> Option<usize> {
+        if let Some(f) = self.known_functions.get(name) {
+            return Some(f.arity);
+        }
+        if let Some(f) = self.user_functions.borrow().get(name) {
+            return Some(f.params.len());
+        }
+        name.starts_with("log_").then_some(1)
+    }
+
+    /// Evaluate `statement` with the variable `name` bound to `value`, which shadows any other
+    /// variable or constant with the same name
+    pub fn eval_with(
+        &self,
+        statement: &AstStatement,
+        name: &str,
+        value: Value,
+    ) -> anyhow::Result<Value> {
+        // these are usually evaluated many times, which would hide the rest of the trace
+        let trace = self.trace.borrow_mut().take();
+        self.scopes
+            .borrow_mut()
+            .push(HashMap::from([(name.to_string(), value)]));
+        let out = self.eval(statement);
+        self.scopes.borrow_mut().pop();
+        *self.trace.borrow_mut() = trace;
+        out
+    }
+
+    /// Get the value of a bound variable, constant, or variable (in that order)
+    pub fn get_variable(&self, v: String) -> anyhow::Result<Value> {
+        if let Some(value) = self.scopes.borrow().iter().rev().find_map(|s| s.get(&v)) {
+            return Ok(value.clone());
+        }
+
+        if let Some(c) = self.const_map.get(v.as_str()) {
+            return Ok(Value::Number(*c));
+        }
+
+        self.variable_map
+            .get(&v)
+            .cloned()
+            .with_context(|| format!("Undeclared variable or constant: '{}'", v))
     }
 
     /// Evaluate an infix expression by converting it to postfix and evaluating it
-    fn eval_infix(&self, expr: &Vec<AstStatement>) -> anyhow::Result<f64> {
+    fn eval_infix(&self, expr: &[AstStatement]) -> anyhow::Result<Value> {
         // Convert the infix expression to postfix
         let pf = AstEvaluator::infix_to_postfix(expr)
             .context("Converting infix expression to postfix")?;
@@ -245,34 +843,43 @@ impl AstEvaluator {
 
         for tok in pf {
             if tok.is_operand() {
-                s.push(tok);
+                // Evaluate the statements, so that we can have recursive evals
+                s.push(
+                    self.eval(&tok)
+                        .with_context(|| format!("Evaluating postfix expression: '{}'", tok))?,
+                );
+            } else if tok.unwrap_operator().is_unary() {
+                let a = s.pop().context("Missing items from postfix eval stack")?;
+                let op = tok.unwrap_operator();
+                let step = self.is_tracing().then(|| format!("{}{}", op, a));
+                s.push(
+                    self.traced(|| step.unwrap_or_default(), || op.eval_unary(a))
+                        .with_context(|| format!("Evaluating operator: '{}'", op))?,
+                );
             } else {
                 // Pop the items from the stack (order matters)
                 let b = s.pop().context("Missing items from postfix eval stack")?;
                 let a = s.pop().context("Missing items from postfix eval stack")?;
 
-                // Evaluate the statements, so that we can have recursive evals
-                let a = self
-                    .eval(&a)
-                    .with_context(|| format!("Evaluating postfix expression: '{}'", a))?;
-                let b = self
-                    .eval(&b)
-                    .with_context(|| format!("Evaluating postfix expression: '{}'", a))?;
-
                 // Push the new computed value back onto the stack
-                s.push(tok.unwrap_operator().eval(a, b).into());
+                let op = tok.unwrap_operator();
+                let step = self.is_tracing().then(|| format!("{} {} {}", a, op, b));
+                s.push(
+                    self.traced(|| step.unwrap_or_default(), || op.eval(a, b))
+                        .with_context(|| format!("Evaluating operator: '{}'", op))?,
+                );
             }
         }
 
         // More than one item left on stack means that we did something wrong
         ensure!(s.len() == 1, "Invalid expression");
 
-        // Evaluate the final statement on the stack, this will be the answer
-        self.eval(&s.pop().expect("checked with above ensure"))
+        // The final value on the stack is the answer
+        Ok(s.pop().expect("checked with above ensure"))
     }
 
     /// Convert a infix expression into postfix notation in order to evaluate it more easily
-    pub fn infix_to_postfix(expr: &Vec<AstStatement>) -> anyhow::Result<Vec<AstStatement>> {
+    pub fn infix_to_postfix(expr: &[AstStatement]) -> anyhow::Result<Vec<AstStatement>> {
         let mut out = Vec::new();
         let mut ops = Vec::new();
 
@@ -288,6 +895,8 @@ impl AstEvaluator {
                 AstStatement::Operator(Operator::LeftParen) => {
                     ops.push(Operator::LeftParen);
                 }
+                // prefix operators apply to the operand which follows, so there is nothing to pop
+                AstStatement::Operator(tok) if tok.is_unary() => ops.push(tok),
                 AstStatement::Operator(tok) => {
                     while !ops.is_empty() && tok.prec() <= ops.last().unwrap().prec() {
                         let o = ops.pop().unwrap();
@@ -310,13 +919,19 @@ impl AstEvaluator {
 
     /// Evaluate a function based on its name.
     ///
-    /// There are a few special cases:
+    /// There is one special case:
     /// - `log_<BASE>(x)` will evaluate to the log using `<BASE>` as its base.
     ///     - i.e. `log_2(x)` will be log base 2
-    /// - `log_B(x, base)` will evaluate to the log using `base` as its base.
-    pub fn eval_function(&self, name: &str, args: Vec<AstStatement>) -> anyhow::Result<f64> {
+    pub fn eval_function(&self, name: &str, args: Vec<AstStatement>) -> anyhow::Result<Value> {
         if let Some(s) = self.known_functions.get(name) {
-            return s(self, args).with_context(|| format!("When evaluating function '{}'", name));
+            return (s.call)(self, args)
+                .with_context(|| format!("When evaluating function '{}'", name));
+        }
+        let user = self.user_functions.borrow().get(name).cloned();
+        if let Some(f) = user {
+            return self
+                .call(name, &f, &args)
+                .with_context(|| format!("When evaluating function '{}'", name));
         }
 
         // Handle log_base functions
@@ -332,20 +947,7 @@ impl AstEvaluator {
                         base
                     );
 
-                    return Ok(self.eval(&args[0])?.log(base));
-                }
-
-                // If the base is "B" use a custom base -- this could be done in the
-                // `known_functions` but meh
-                if base == "B" {
-                    ensure!(
-                        // Ensure arg length requirements
-                        args.len() == 2,
-                        "Expected 2 args, found {}. Usage: `log_B(x, base)`",
-                        args.len()
-                    );
-
-                    return Ok(self.eval(&args[0])?.log(self.eval(&args[1])?));
+                    return self.eval(&args[0])?.map_number(|n| n.log(base));
                 }
 
                 bail!(
@@ -1,6 +1,14 @@
 use std::fmt::Display;
 
-use super::AstStatement;
+use anyhow::{bail, ensure};
+
+use super::{
+    value::{Value, MAX_EXACT_F64},
+    AstStatement,
+};
+
+/// The largest list that a range is allowed to produce
+const MAX_RANGE_LEN: f64 = 10_000_000.0;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Operator {
@@ -26,9 +34,13 @@ pub enum Operator {
     BitOr,
     /// **
     Exponent,
+    /// ..
+    Range,
+    /// Unary minus: -x
+    Negate,
+    /// An equation: a = b, which is 1 when both sides are equal and 0 otherwise
+    Equals,
 
-    // /// =
-    // Assign,
     /// (
     LeftParen,
     /// )
@@ -46,22 +58,26 @@ pub enum Operator {
 impl Operator {
     pub fn prec(&self) -> i64 {
         match self {
-            Self::Add => 4,
-            Self::Subtract => 4,
+            Self::Add => 5,
+            Self::Subtract => 5,
 
-            Self::Multiply => 5,
-            Self::Divide => 5,
+            Self::Multiply => 6,
+            Self::Divide => 6,
 
-            Self::Modulo => 5,
+            Self::Modulo => 6,
             //Self::Tilda => 1,
             //Self::Bang => 1,
-            Self::Xor => 2,
-            Self::BitAnd => 3,
-            Self::BitOr => 1,
+            Self::Xor => 3,
+            Self::BitAnd => 4,
+            Self::BitOr => 2,
+
+            // Binds tighter than everything except `**`, so `-x ** 2` is `-(x ** 2)`
+            Self::Negate => 7,
+            Self::Exponent => 8,
 
-            Self::Exponent => 6,
+            Self::Range => 1,
 
-            //Self::Assign => 1,
+            Self::Equals => 1,
             Self::LeftParen => 0,
             Self::RightParen => 0,
             //Self::LeftCurlyBracket => 0,
@@ -71,7 +87,170 @@ impl Operator {
         }
     }
 
-    pub fn eval(&self, a: f64, b: f64) -> f64 {
+    /// Check if this is a prefix operator which only takes one operand
+    pub fn is_unary(&self) -> bool {
+        *self == Self::Negate
+    }
+
+    /// Apply this unary operator to a value
+    pub fn eval_unary(&self, a: Value) -> anyhow::Result<Value> {
+        match self {
+            // subtracting keeps exact integers exact, and works element-wise
+            Self::Negate => Self::Subtract.eval(Value::Number(0.0), a),
+            _ => bail!("'{}' is not a unary operator", self),
+        }
+    }
+
+    /// Apply this operator to two values.  Lists are operated on element-wise, and a number
+    /// paired with a list is applied to every item in the list.
+    pub fn eval(&self, a: Value, b: Value) -> anyhow::Result<Value> {
+        // Characters are just their code point when doing maths: `'A' + 1`
+        let (a, b) = (a.into_code_point(), b.into_code_point());
+
+        if *self == Self::Range {
+            return Self::range(a.as_number()?, b.as_number()?);
+        }
+        if *self == Self::Equals {
+            return Ok(Value::Number(if a == b { 1.0 } else { 0.0 }));
+        }
+
+        Ok(match (a, b) {
+            (a @ (Value::Number(_) | Value::Int(_)), b @ (Value::Number(_) | Value::Int(_))) => {
+                self.eval_scalar(&a, &b)?
+            }
+
+            // Matrices use linear algebra for `*` and `**`, and are element-wise otherwise
+            (Value::Matrix(a), Value::Matrix(b)) => match self {
+                Self::Multiply => a.mul(&b)?.into(),
+                _ => a.zip_with(&b, |a, b| self.eval_number(a, b))?.into(),
+            },
+            (Value::Matrix(a), b @ (Value::Number(_) | Value::Int(_))) => match self {
+                Self::Exponent => {
+                    let b = b.as_number()?;
+                    ensure!(
+                        b.fract() == 0.0,
+                        "Matrix exponent must be an integer: {}",
+                        b
+                    );
+                    a.pow(b as i64)?.into()
+                }
+                _ => {
+                    let b = b.as_number()?;
+                    a.map(|a| self.eval_number(a, b)).into()
+                }
+            },
+            (a @ (Value::Number(_) | Value::Int(_)), Value::Matrix(b)) => {
+                let a = a.as_number()?;
+                b.map(|b| self.eval_number(a, b)).into()
+            }
+            (Value::Matrix(a), b @ Value::List(_)) if *self == Self::Multiply => {
+                a.mul_vec(&b.as_vector()?)?.into()
+            }
+            (a @ Value::List(_), Value::Matrix(b)) if *self == Self::Multiply => {
+                b.transpose().mul_vec(&a.as_vector()?)?.into()
+            }
+            (a @ Value::Matrix(_), b) => self.eval(a.into_rows(), b)?,
+            (a, b @ Value::Matrix(_)) => self.eval(a, b.into_rows())?,
+
+            (Value::List(a), Value::List(b)) => {
+                ensure!(
+                    a.len() == b.len(),
+                    "Cannot apply '{}' to lists of different lengths ({} and {})",
+                    self,
+                    a.len(),
+                    b.len()
+                );
+                Value::List(
+                    a.into_iter()
+                        .zip(b)
+                        .map(|(a, b)| self.eval(a, b))
+                        .collect::<anyhow::Result<_>>()?,
+                )
+            }
+            (Value::List(a), b) => Value::List(
+                a.into_iter()
+                    .map(|a| self.eval(a, b.clone()))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            (a, Value::List(b)) => Value::List(
+                b.into_iter()
+                    .map(|b| self.eval(a.clone(), b))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            (Value::Expr(e), _) | (_, Value::Expr(e)) => {
+                bail!("Cannot use the expression '{}' as a value", e)
+            }
+            (Value::Char(_), _) | (_, Value::Char(_)) => {
+                unreachable!("characters are converted to code points above")
+            }
+        })
+    }
+
+    /// Create the list `[start, start + 1, ...]` up to, but not including, `end`
+    fn range(start: f64, end: f64) -> anyhow::Result<Value> {
+        if !start.is_finite() || !end.is_finite() {
+            bail!("Range bounds must be finite: {}..{}", start, end);
+        }
+        // past this, adding 1 doesn't always give the next integer
+        let exact = |n: f64| n.fract() == 0.0 && n.abs() <= MAX_EXACT_F64 as f64;
+        ensure!(
+            exact(start) && exact(end),
+            "Range bounds must be integers no larger than {}: {}..{}",
+            MAX_EXACT_F64,
+            start,
+            end
+        );
+        ensure!(
+            end - start <= MAX_RANGE_LEN,
+            "Range {}..{} is too large, the maximum length is {}",
+            start,
+            end,
+            MAX_RANGE_LEN
+        );
+
+        let len = (end - start).max(0.0) as usize;
+        Ok((0..len)
+            .map(|i| start + i as f64)
+            .collect::<Vec<_>>()
+            .into())
+    }
+
+    /// Apply this operator to two numbers, using exact integer maths if both are integers
+    fn eval_scalar(&self, a: &Value, b: &Value) -> anyhow::Result<Value> {
+        if let (Some(a), Some(b)) = (a.to_exact_int(), b.to_exact_int()) {
+            if let Some(n) = self.eval_int(a, b) {
+                return Ok(Value::int(n));
+            }
+        }
+        Ok(self.eval_number(a.as_number()?, b.as_number()?).into())
+    }
+
+    /// Apply this operator to two integers, or `None` if the result is not an integer, or does
+    /// not fit in an i128
+    fn eval_int(&self, a: i128, b: i128) -> Option<i128> {
+        match self {
+            Self::Add => a.checked_add(b),
+            Self::Subtract => a.checked_sub(b),
+            Self::Multiply => a.checked_mul(b),
+            Self::Divide => match a.checked_rem(b)? {
+                0 => a.checked_div(b),
+                _ => None,
+            },
+            Self::Modulo => a.checked_rem(b),
+            Self::Xor => Some(a ^ b),
+            Self::BitAnd => Some(a & b),
+            Self::BitOr => Some(a | b),
+            Self::Exponent => a.checked_pow(b.try_into().ok()?),
+
+            Self::Range => None,
+            Self::Equals => None,
+            Self::Negate => None,
+            Self::LeftParen => None,
+            Self::RightParen => None,
+        }
+    }
+
+    fn eval_number(&self, a: f64, b: f64) -> f64 {
         match self {
             Self::Add => a + b,
             Self::Subtract => a - b,
@@ -83,6 +262,9 @@ impl Operator {
             Self::BitOr => (a as i64 | b as i64) as f64,
             Self::Exponent => a.powf(b),
 
+            Self::Range => unreachable!(),
+            Self::Equals => unreachable!(),
+            Self::Negate => unreachable!(),
             Self::LeftParen => unreachable!(),
             Self::RightParen => unreachable!(),
             //Self::LeftCurlyBracket => unreachable!(),
@@ -116,7 +298,9 @@ impl Display for Operator {
                 Self::BitAnd => "&",
                 Self::BitOr => "|",
                 Self::Exponent => "**",
-                //Self::Assign => "=",
+                Self::Range => "..",
+                Self::Negate => "-",
+                Self::Equals => "=",
                 Self::LeftParen => "(",
                 Self::RightParen => ")",
                 //Self::LeftCurlyBracket => "{",
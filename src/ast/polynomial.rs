@@ -0,0 +1,398 @@
+use anyhow::{bail, ensure};
+
+use super::{op::Operator, symbolic::Expr, value::Value};
+
+/// The maximum number of iterations of the Durand-Kerner method
+const MAX_ITERATIONS: usize = 1000;
+/// The angle of the first starting point of the Durand-Kerner method
+const SEED_ANGLE: f64 = 0.4;
+/// Roots which move less than this in an iteration have converged
+const TOLERANCE: f64 = 1e-14;
+/// The maximum number of iterations of Newton's method when refining a root
+const MAX_NEWTON_ITERATIONS: usize = 100;
+/// Values of a polynomial which are smaller than this (relative to the size of its terms) are zero
+const ROOT_TOLERANCE: f64 = 1e-12;
+/// The highest degree of polynomial which can be solved, since finding the roots takes time
+/// proportional to the cube of the degree
+pub const MAX_DEGREE: usize = 100;
+/// Parts of a root which are smaller than this (relative to the root) are rounding errors
+const NOISE: f64 = 1e-10;
+
+/// A complex number, used for the roots of polynomials
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    pub fn real(re: f64) -> Self {
+        Self { re, im: 0.0 }
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+
+    fn div(self, other: Complex) -> Complex {
+        let d = other.re * other.re + other.im * other.im;
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / d,
+            (self.im * other.re - self.re * other.im) / d,
+        )
+    }
+
+    /// Remove any part which is tiny compared to the whole number, so that `2 + 1e-17i` is `2`
+    fn denoise(self) -> Complex {
+        let scale = self.abs().max(1.0) * NOISE;
+        let clean = |x: f64| {
+            if x.abs() < scale {
+                0.0
+            } else if (x - x.round()).abs() < scale {
+                x.round()
+            } else {
+                x
+            }
+        };
+        Complex::new(clean(self.re), clean(self.im))
+    }
+}
+
+/// Complex numbers are shown using the variable `i`: `-1 + 2 * i`
+impl From<Complex> for Value {
+    fn from(c: Complex) -> Self {
+        if c.im == 0.0 {
+            return c.re.into();
+        }
+
+        // `b * i`, leaving out the `1` in `1 * i`
+        let imaginary = |b: f64| {
+            let i = Expr::Variable("i".into());
+            if b == 1.0 {
+                i
+            } else if b == -1.0 {
+                Expr::Unary(Operator::Negate, Box::new(i))
+            } else {
+                Expr::Binary(Operator::Multiply, Box::new(b.into()), Box::new(i))
+            }
+        };
+
+        Value::Expr(Box::new(if c.re == 0.0 {
+            imaginary(c.im)
+        } else if c.im < 0.0 {
+            Expr::Binary(
+                Operator::Subtract,
+                Box::new(c.re.into()),
+                Box::new(imaginary(-c.im)),
+            )
+        } else {
+            Expr::Binary(
+                Operator::Add,
+                Box::new(c.re.into()),
+                Box::new(imaginary(c.im)),
+            )
+        }))
+    }
+}
+
+/// Evaluate the polynomial with coefficients `coefs` (lowest power first) at `x`
+fn eval(coefs: &[Complex], x: Complex) -> Complex {
+    coefs
+        .iter()
+        .rev()
+        .fold(Complex::real(0.0), |acc, c| acc.mul(x).add(*c))
+}
+
+/// Find every root of the polynomial with coefficients `coefs` (lowest power first), including
+/// repeated and complex roots.  Real roots come first, in ascending order.
+///
+/// Linear and quadratic polynomials are solved exactly, and higher degrees use the Durand-Kerner
+/// method.
+pub fn roots(coefs: &[f64]) -> anyhow::Result<Vec<Complex>> {
+    ensure!(
+        coefs.iter().all(|c| c.is_finite()),
+        "Coefficients must be finite: {:?}",
+        coefs
+    );
+
+    // drop any leading zeros, so the highest power has a coefficient
+    let len = coefs.iter().rposition(|c| *c != 0.0).map_or(0, |i| i + 1);
+    let coefs = &coefs[..len];
+
+    // `x = 0` is a root for each power of `x` which can be factored out
+    let zeros = coefs.iter().position(|c| *c != 0.0).unwrap_or(0);
+    let mut out = vec![Complex::real(0.0); zeros];
+    let coefs = &coefs[zeros..];
+
+    match coefs {
+        [] => bail!("Every value is a solution"),
+        [_] if zeros == 0 => bail!("There are no solutions"),
+        [_] => {}
+        [c, b] => out.push(Complex::real(-c / b)),
+        [c, b, a] => out.extend(quadratic(*a, *b, *c)),
+        _ => out.extend(durand_kerner(coefs)?),
+    }
+
+    out.sort_by(|a, b| {
+        (a.im != 0.0, a.re, a.im)
+            .partial_cmp(&(b.im != 0.0, b.re, b.im))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(out)
+}
+
+/// Solve `a x^2 + b x + c = 0`, avoiding the cancellation in the usual formula
+fn quadratic(a: f64, b: f64, c: f64) -> [Complex; 2] {
+    let disc = b * b - 4.0 * a * c;
+    if disc < 0.0 {
+        let (re, im) = (-b / (2.0 * a), (-disc).sqrt() / (2.0 * a));
+        return [Complex::new(re, -im.abs()), Complex::new(re, im.abs())];
+    }
+
+    let q = -0.5 * (b + b.signum() * disc.sqrt());
+    if q == 0.0 {
+        return [Complex::real(0.0); 2];
+    }
+    [Complex::real(q / a), Complex::real(c / q)].map(Complex::denoise)
+}
+
+/// Find the roots of a polynomial of degree 3 or more by refining a guess for every root at once
+fn durand_kerner(coefs: &[f64]) -> anyhow::Result<Vec<Complex>> {
+    let lead = coefs[coefs.len() - 1];
+    let monic: Vec<_> = coefs.iter().map(|c| Complex::real(c / lead)).collect();
+    let degree = monic.len() - 1;
+
+    // start evenly spaced around a circle which holds every root (Fujiwara's bound), turned so
+    // that none of the starting points are real or symmetric about the real line
+    let radius = (0..degree)
+        .map(|i| {
+            let c = if i == 0 {
+                monic[0].abs() / 2.0
+            } else {
+                monic[i].abs()
+            };
+            c.powf(1.0 / (degree - i) as f64)
+        })
+        .fold(0.0, f64::max)
+        * 2.0;
+    let mut roots: Vec<_> = (0..degree)
+        .map(|k| {
+            let angle = std::f64::consts::TAU * k as f64 / degree as f64 + SEED_ANGLE;
+            Complex::new(radius * angle.cos(), radius * angle.sin())
+        })
+        .collect();
+
+    for _ in 0..MAX_ITERATIONS {
+        let mut change: f64 = 0.0;
+        for i in 0..degree {
+            let denom = (0..degree)
+                .filter(|j| *j != i)
+                .fold(Complex::real(1.0), |acc, j| acc.mul(roots[i].sub(roots[j])));
+            let step = eval(&monic, roots[i]).div(denom);
+            roots[i] = roots[i].sub(step);
+            change = change.max(step.abs() / roots[i].abs().max(1.0));
+        }
+        if change < TOLERANCE {
+            break;
+        }
+    }
+    ensure!(
+        roots.iter().all(|r| r.re.is_finite() && r.im.is_finite()),
+        "Failed to find the roots after {} iterations",
+        MAX_ITERATIONS
+    );
+    Ok(repeated(&monic, roots))
+}
+
+/// The coefficients of the derivative of a polynomial
+fn derivative(coefs: &[Complex]) -> Vec<Complex> {
+    coefs
+        .iter()
+        .enumerate()
+        .skip(1)
+        .map(|(i, c)| c.mul(Complex::real(i as f64)))
+        .collect()
+}
+
+/// Refine a root of `p` with Newton's method, or `None` if it doesn't converge
+fn newton(p: &[Complex], dp: &[Complex], mut z: Complex) -> Option<Complex> {
+    for _ in 0..MAX_NEWTON_ITERATIONS {
+        let d = eval(dp, z);
+        if d.abs() == 0.0 {
+            break;
+        }
+        let step = eval(p, z).div(d);
+        z = z.sub(step);
+        if step.abs() < TOLERANCE * z.abs().max(1.0) {
+            break;
+        }
+    }
+    (z.re.is_finite() && z.im.is_finite()).then_some(z)
+}
+
+/// Check if `p(z)` is zero, allowing for the rounding errors in evaluating it
+fn is_zero(p: &[Complex], z: Complex) -> bool {
+    let scale = p.iter().rev().fold(0.0, |acc, c| acc * z.abs() + c.abs());
+    eval(p, z).abs() <= ROOT_TOLERANCE * scale
+}
+
+/// Find the repeated roots among the roots of `monic` found by Durand-Kerner, and refine every
+/// root.
+///
+/// A root repeated `m` times is very sensitive to rounding, so it's found as a spread out group
+/// of `m` roots around the true root.  Each root is grouped with its nearest neighbours, trying
+/// the largest groups first, and a group of `m` is a repeated root if the first `m - 1`
+/// derivatives are all zero near its centre.  The root is then a simple root of the `m - 1`th
+/// derivative, which Newton's method can find accurately.
+fn repeated(monic: &[Complex], mut roots: Vec<Complex>) -> Vec<Complex> {
+    let mut derivs = vec![monic.to_vec()];
+    for _ in 0..roots.len() {
+        let d = derivative(&derivs[derivs.len() - 1]);
+        derivs.push(d);
+    }
+
+    let mut out = Vec::with_capacity(roots.len());
+    while let Some(r) = roots.pop() {
+        let mut near: Vec<_> = (0..roots.len()).collect();
+        near.sort_by(|a, b| {
+            let (a, b) = (roots[*a].sub(r).abs(), roots[*b].sub(r).abs());
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let group = (1..=near.len()).rev().find_map(|n| {
+            let m = n + 1;
+            let centre = near[..n]
+                .iter()
+                .fold(r, |acc, j| acc.add(roots[*j]))
+                .div(Complex::real(m as f64));
+            let spread = near[..n]
+                .iter()
+                .map(|j| roots[*j].sub(centre).abs())
+                .fold(r.sub(centre).abs(), f64::max);
+            let z = newton(&derivs[m - 1], &derivs[m], centre)?;
+            let close = z.sub(centre).abs() <= 2.0 * spread + TOLERANCE * z.abs().max(1.0);
+            (close && (0..m).all(|k| is_zero(&derivs[k], z))).then_some((z, n))
+        });
+
+        match group {
+            Some((z, n)) => {
+                let mut taken = near[..n].to_vec();
+                taken.sort_unstable_by(|a, b| b.cmp(a));
+                for j in taken {
+                    roots.remove(j);
+                }
+                out.extend(std::iter::repeat(z).take(n + 1));
+            }
+            None => out.push(newton(&derivs[0], &derivs[1], r).unwrap_or(r)),
+        }
+    }
+    out.into_iter().map(Complex::denoise).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn real(roots: &[Complex]) -> Vec<f64> {
+        roots
+            .iter()
+            .map(|r| {
+                assert_eq!(r.im, 0.0, "{:?} is not real", r);
+                r.re
+            })
+            .collect()
+    }
+
+    #[test]
+    fn low_degree() {
+        assert_eq!(real(&roots(&[-6.0, 3.0]).unwrap()), vec![2.0]);
+        assert_eq!(real(&roots(&[6.0, -5.0, 1.0]).unwrap()), vec![2.0, 3.0]);
+        assert_eq!(real(&roots(&[0.0, 0.0, 1.0]).unwrap()), vec![0.0, 0.0]);
+        assert_eq!(
+            roots(&[2.0, 2.0, 1.0]).unwrap(),
+            vec![Complex::new(-1.0, -1.0), Complex::new(-1.0, 1.0)]
+        );
+        // leading zeros are ignored
+        assert_eq!(real(&roots(&[-1.0, 1.0, 0.0]).unwrap()), vec![1.0]);
+
+        assert!(roots(&[0.0, 0.0]).is_err());
+        assert!(roots(&[1.0]).is_err());
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(Value::from(Complex::real(2.0)), 2.0);
+        assert_eq!(
+            Value::from(Complex::new(-1.0, 2.0)).to_string(),
+            "-1 + 2 * i"
+        );
+        assert_eq!(Value::from(Complex::new(0.5, -1.0)).to_string(), "0.5 - i");
+        assert_eq!(Value::from(Complex::new(0.0, -1.0)).to_string(), "-i");
+    }
+
+    #[test]
+    fn high_degree() {
+        // (x - 1)(x - 2)(x - 3)
+        assert_eq!(
+            real(&roots(&[-6.0, 11.0, -6.0, 1.0]).unwrap()),
+            vec![1.0, 2.0, 3.0]
+        );
+        // x^4 - 1
+        assert_eq!(
+            roots(&[-1.0, 0.0, 0.0, 0.0, 1.0]).unwrap(),
+            vec![
+                Complex::real(-1.0),
+                Complex::real(1.0),
+                Complex::new(0.0, -1.0),
+                Complex::new(0.0, 1.0)
+            ]
+        );
+        // (x - 1)^4, where the repeated root stops it from converging quickly
+        assert_eq!(
+            real(&roots(&[1.0, -4.0, 6.0, -4.0, 1.0]).unwrap()),
+            vec![1.0; 4]
+        );
+        // (x + 1)^8, which is spread out by 0.1 before the repeated root is found
+        assert_eq!(
+            real(&roots(&[1.0, 8.0, 28.0, 56.0, 70.0, 56.0, 28.0, 8.0, 1.0]).unwrap()),
+            vec![-1.0; 8]
+        );
+        // x^3 (x - 5)
+        assert_eq!(
+            real(&roots(&[0.0, 0.0, 0.0, -5.0, 1.0]).unwrap()),
+            vec![0.0, 0.0, 0.0, 5.0]
+        );
+    }
+
+    #[test]
+    fn max_degree() {
+        // x^100 + x + 1
+        let mut coefs = vec![0.0; MAX_DEGREE + 1];
+        (coefs[0], coefs[1], coefs[MAX_DEGREE]) = (1.0, 1.0, 1.0);
+        let found = roots(&coefs).unwrap();
+        assert_eq!(found.len(), MAX_DEGREE);
+        let p: Vec<_> = coefs.iter().map(|c| Complex::real(*c)).collect();
+        for r in found {
+            assert!(is_zero(&p, r), "{:?} is not a root", r);
+        }
+    }
+}
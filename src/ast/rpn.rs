@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use anyhow::{ensure, Context};
+
+use super::{op::Operator, value::Value, AstEvaluator, AstStatement};
+
+/// The stack for Reverse Polish Notation input, where each word of a line is applied to the
+/// stack in turn: `3 4 + 2 *` leaves `14` on the stack.
+///
+/// Values are pushed, operators and functions take their arguments from the top of the stack and
+/// push their result, and a few words rearrange the stack:
+///
+/// - `dup` copies the top value
+/// - `swap` swaps the top two values
+/// - `drop` removes the top value
+/// - `roll` moves the top value to the bottom
+/// - `clear` removes every value
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Stack {
+    /// The values on the stack, with the top last
+    pub values: Vec<Value>,
+}
+
+impl Stack {
+    /// Apply each word in `line` to the stack.  If any word fails, the stack is left as it was
+    /// before the line.
+    ///
+    /// `signed_width` is used to read hex and binary numbers in the same way as infix input.
+    pub fn eval_line(
+        &mut self,
+        eval: &AstEvaluator,
+        line: &str,
+        signed_width: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let mut values = self.values.clone();
+        for word in words(line) {
+            Self::eval_word(&mut values, eval, word, signed_width)
+                .with_context(|| format!("Evaluating RPN word: '{}'", word))?;
+        }
+        self.values = values;
+        Ok(())
+    }
+
+    fn eval_word(
+        values: &mut Vec<Value>,
+        eval: &AstEvaluator,
+        word: &str,
+        signed_width: Option<u32>,
+    ) -> anyhow::Result<()> {
+        let op = match word {
+            "dup" => {
+                let top = values.last().context("The stack is empty")?.clone();
+                values.push(top);
+                return Ok(());
+            }
+            "swap" => {
+                ensure!(values.len() >= 2, "Expected 2 values on the stack");
+                let len = values.len();
+                values.swap(len - 1, len - 2);
+                return Ok(());
+            }
+            "drop" => {
+                values.pop().context("The stack is empty")?;
+                return Ok(());
+            }
+            "roll" => {
+                if !values.is_empty() {
+                    values.rotate_right(1);
+                }
+                return Ok(());
+            }
+            "clear" => {
+                values.clear();
+                return Ok(());
+            }
+
+            "+" => Operator::Add,
+            "-" => Operator::Subtract,
+            "*" => Operator::Multiply,
+            "/" => Operator::Divide,
+            "%" => Operator::Modulo,
+            "**" => Operator::Exponent,
+            "^" => Operator::Xor,
+            "&" => Operator::BitAnd,
+            "|" => Operator::BitOr,
+            ".." => Operator::Range,
+            "=" => Operator::Equals,
+            "neg" => Operator::Negate,
+            "!" => return Self::call(values, eval, "factorial", 1),
+
+            _ => {
+                let (name, arity) = match word.split_once('/') {
+                    Some((name, n)) if !name.is_empty() => (
+                        name,
+                        Some(
+                            n.parse()
+                                .with_context(|| format!("Invalid argument count: '{}'", n))?,
+                        ),
+                    ),
+                    _ => (word, None),
+                };
+                // functions take as many values as they have arguments, `log_2` takes one, and
+                // functions which take any number of values (like `sum`) take the count given
+                // after their name, `sum/3`
+                if let Some(default) = eval.arity(name) {
+                    return Self::call(values, eval, name, arity.unwrap_or(default));
+                }
+
+                // anything else is a value, like `1.5`, `pi`, or `[1, 2]`
+                let statement = match signed_width {
+                    Some(width) => AstStatement::parse_signed(word, width)?,
+                    None => word.parse()?,
+                };
+                values.push(eval.eval(&statement)?);
+                return Ok(());
+            }
+        };
+
+        let result = if op.is_unary() {
+            let a = values.pop().context("The stack is empty")?;
+            op.eval_unary(a)?
+        } else {
+            let args = Self::pop(values, 2)?;
+            let [a, b]: [Value; 2] = args.try_into().expect("took 2 values");
+            op.eval(a, b)?
+        };
+        values.push(result);
+        Ok(())
+    }
+
+    /// Take the top `n` values from the stack, in the order they were pushed
+    fn pop(values: &mut Vec<Value>, n: usize) -> anyhow::Result<Vec<Value>> {
+        ensure!(
+            values.len() >= n,
+            "Expected {} value(s) on the stack, found {}",
+            n,
+            values.len()
+        );
+        Ok(values.split_off(values.len() - n))
+    }
+
+    /// Call the function `name` with the top `arity` values from the stack
+    fn call(
+        values: &mut Vec<Value>,
+        eval: &AstEvaluator,
+        name: &str,
+        arity: usize,
+    ) -> anyhow::Result<()> {
+        ensure!(arity > 0, "Functions take at least one value: '{}/0'", name);
+        let args = Self::pop(values, arity)?;
+
+        // functions take statements, so each value is bound to a variable which can't be written
+        // in an expression
+        let names: Vec<_> = (0..arity).map(|i| format!("${}", i)).collect();
+        let params = names
+            .iter()
+            .map(|n| AstStatement::Variable(n.clone()))
+            .collect();
+        eval.scopes
+            .borrow_mut()
+            .push(names.into_iter().zip(args).collect::<HashMap<_, _>>());
+        let out = eval.eval_function(name, params);
+        eval.scopes.borrow_mut().pop();
+
+        values.push(out?);
+        Ok(())
+    }
+}
+
+/// Split `line` into words on whitespace, except inside brackets, so that a list like `[1, 2]`
+/// is a single word
+fn words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let (mut start, mut depth) = (None, 0usize);
+    for (i, c) in line.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(start) = start.take() {
+                    words.push(&line[start..i]);
+                }
+                continue;
+            }
+            _ => {}
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(start) = start {
+        words.push(&line[start..]);
+    }
+    words
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rpn(line: &str) -> anyhow::Result<Vec<String>> {
+        let eval = AstEvaluator::new();
+        let mut stack = Stack::default();
+        stack.eval_line(&eval, line, None)?;
+        Ok(stack.values.iter().map(ToString::to_string).collect())
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(rpn("3 4 + 2 *").unwrap(), vec!["14"]);
+        assert_eq!(rpn("1 -2 -").unwrap(), vec!["3"]);
+        assert_eq!(rpn("2 neg 3 **").unwrap(), vec!["-8"]);
+        assert_eq!(rpn("1 2 3").unwrap(), vec!["1", "2", "3"]);
+        assert_eq!(rpn("5 !").unwrap(), vec!["120"]);
+        assert_eq!(rpn("pi 2 / sin").unwrap(), vec!["1"]);
+        assert_eq!(rpn("12 18 gcd").unwrap(), vec!["6"]);
+        assert_eq!(rpn("1 2 3 sum/3").unwrap(), vec!["6"]);
+        assert_eq!(rpn("[1,2,3] sum").unwrap(), vec!["6"]);
+        assert_eq!(rpn("[1, 2, 3] sum").unwrap(), vec!["6"]);
+        assert_eq!(rpn("[[1, 2], [3, 4]]  det").unwrap(), vec!["-2"]);
+        assert_eq!(rpn("2 10 1000 modpow").unwrap(), vec!["24"]);
+        assert_eq!(rpn("8 2 log_B").unwrap(), vec!["3"]);
+
+        assert!(rpn("1 +").is_err());
+        assert!(rpn("sqrt").is_err());
+        assert!(rpn("1 nope").is_err());
+        assert!(rpn("2 10 modpow").is_err());
+    }
+
+    #[test]
+    fn stack() {
+        assert_eq!(rpn("2 dup *").unwrap(), vec!["4"]);
+        assert_eq!(rpn("1 2 swap -").unwrap(), vec!["1"]);
+        assert_eq!(rpn("1 2 drop").unwrap(), vec!["1"]);
+        assert_eq!(rpn("1 2 3 roll").unwrap(), vec!["3", "1", "2"]);
+        assert_eq!(rpn("1 2 clear 3").unwrap(), vec!["3"]);
+
+        assert!(rpn("drop").is_err());
+        assert!(rpn("1 swap").is_err());
+
+        // a line which fails leaves the stack untouched
+        let eval = AstEvaluator::new();
+        let mut stack = Stack::default();
+        stack.eval_line(&eval, "1 2", None).unwrap();
+        assert!(stack.eval_line(&eval, "3 + + +", None).is_err());
+        assert_eq!(stack.values, vec![Value::from(1.0), Value::from(2.0)]);
+    }
+}
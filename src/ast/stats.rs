@@ -0,0 +1,425 @@
+use std::f64::consts::{PI, SQRT_2};
+
+use anyhow::{bail, ensure};
+
+/// Find the arithmetic mean
+///
+/// Note: `data` _must_ not be empty, or the result will be NaN
+pub fn mean(data: &[f64]) -> f64 {
+    data.iter().sum::<f64>() / data.len() as f64
+}
+
+/// Find the median, averaging the two middle values if there are an even number of values
+pub fn median(data: &[f64]) -> f64 {
+    percentile(data, 50.0)
+}
+
+/// Find the most common value, preferring the smallest value if there is a tie
+pub fn mode(data: &[f64]) -> f64 {
+    let mut sorted = data.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let mut best = (sorted[0], 0);
+    let mut i = 0;
+    while i < sorted.len() {
+        let run = sorted[i..].iter().take_while(|n| **n == sorted[i]).count();
+        if run > best.1 {
+            best = (sorted[i], run);
+        }
+        i += run.max(1);
+    }
+    best.0
+}
+
+/// Find the variance, using Bessel's correction if `sample` is true
+pub fn variance(data: &[f64], sample: bool) -> f64 {
+    let mean = mean(data);
+    let sq_diff = data.iter().map(|n| (n - mean).powi(2)).sum::<f64>();
+    if sample {
+        sq_diff / (data.len() - 1) as f64
+    } else {
+        sq_diff / data.len() as f64
+    }
+}
+
+/// Find the `p`th percentile (`0..=100`), linearly interpolating between the closest values
+pub fn percentile(data: &[f64], p: f64) -> f64 {
+    let mut sorted = data.to_vec();
+    sorted.sort_by(f64::total_cmp);
+
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * rank.fract()
+}
+
+/// Find the geometric mean
+pub fn geomean(data: &[f64]) -> f64 {
+    (data.iter().map(|n| n.ln()).sum::<f64>() / data.len() as f64).exp()
+}
+
+/// Find the harmonic mean
+pub fn harmean(data: &[f64]) -> f64 {
+    data.len() as f64 / data.iter().map(|n| 1.0 / n).sum::<f64>()
+}
+
+/// The complementary error function: `1 - erf(x)`
+fn erfc(x: f64) -> f64 {
+    if x < 0.0 {
+        return 2.0 - erfc(-x);
+    }
+
+    if x < 3.0 {
+        // erf(x) = 2/sqrt(pi) * e^(-x^2) * sum(2^n x^(2n + 1) / (1 * 3 * ... * (2n + 1)))
+        let mut term = x;
+        let mut sum = x;
+        let mut n = 0.0;
+        while term > sum * f64::EPSILON {
+            n += 1.0;
+            term *= 2.0 * x * x / (2.0 * n + 1.0);
+            sum += term;
+        }
+        1.0 - 2.0 / PI.sqrt() * (-x * x).exp() * sum
+    } else {
+        // Continued fraction, evaluated from the tail:
+        // erfc(x) = e^(-x^2)/sqrt(pi) * 1/(x + (1/2)/(x + 1/(x + (3/2)/(x + ...))))
+        let mut frac = x;
+        for k in (1..60).rev() {
+            frac = x + (k as f64 / 2.0) / frac;
+        }
+        (-x * x).exp() / PI.sqrt() / frac
+    }
+}
+
+pub fn normal_pdf(x: f64, mu: f64, sigma: f64) -> f64 {
+    let z = (x - mu) / sigma;
+    (-z * z / 2.0).exp() / (sigma * (2.0 * PI).sqrt())
+}
+
+pub fn normal_cdf(x: f64, mu: f64, sigma: f64) -> f64 {
+    erfc(-(x - mu) / (sigma * SQRT_2)) / 2.0
+}
+
+/// Find `x` such that `normal_cdf(x, mu, sigma) == p`
+pub fn normal_inv(p: f64, mu: f64, sigma: f64) -> anyhow::Result<f64> {
+    ensure!(
+        (0.0..=1.0).contains(&p),
+        "Probability must be in 0..=1: {}",
+        p
+    );
+    if p == 0.0 {
+        return Ok(f64::NEG_INFINITY);
+    }
+    if p == 1.0 {
+        return Ok(f64::INFINITY);
+    }
+
+    // Peter Acklam's rational approximation for the inverse of the standard normal cdf
+    const A: [f64; 6] = [
+        -3.969683028665376e+01,
+        2.209460984245205e+02,
+        -2.759285104469687e+02,
+        1.38357751867269e+02,
+        -3.066479806614716e+01,
+        2.506628277459239e+00,
+    ];
+    const B: [f64; 5] = [
+        -5.447609879822406e+01,
+        1.615858368580409e+02,
+        -1.556989798598866e+02,
+        6.680131188771972e+01,
+        -1.328068155288572e+01,
+    ];
+    const C: [f64; 6] = [
+        -7.784894002430293e-03,
+        -3.223964580411365e-01,
+        -2.400758277161838e+00,
+        -2.549732539343734e+00,
+        4.374664141464968e+00,
+        2.938163982698783e+00,
+    ];
+    const D: [f64; 4] = [
+        7.784695709041462e-03,
+        3.224671290700398e-01,
+        2.445134137142996e+00,
+        3.754408661907416e+00,
+    ];
+    const P_LOW: f64 = 0.02425;
+
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+
+    let mut z = if p < P_LOW {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - P_LOW {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    };
+
+    // Refine the approximation to full precision using Halley's method
+    for _ in 0..2 {
+        let e = normal_cdf(z, 0.0, 1.0) - p;
+        let u = e * (2.0 * PI).sqrt() * (z * z / 2.0).exp();
+        z -= u / (1.0 + z * u / 2.0);
+    }
+
+    Ok(mu + sigma * z)
+}
+
+/// The most steps the inverse of a discrete distribution takes from its first guess
+const MAX_STEPS: u64 = 1_000_000;
+
+/// The largest `n` for which `binomial_pdf` finds `nCr` directly, which is exact for small `n`
+/// and can't overflow
+const EXACT_BINOMIAL: u64 = 1000;
+
+/// ln(n!)
+fn ln_factorial(n: u64) -> f64 {
+    (2..=n).map(|i| (i as f64).ln()).sum()
+}
+
+/// The error in Stirling's approximation of `n!`: `ln(n!) - ln(sqrt(2 pi n) (n / e)^n)`, for
+/// `n >= 1`
+fn stirling_error(n: f64) -> f64 {
+    if n <= 15.0 {
+        ln_factorial(n as u64) - (n + 0.5) * n.ln() + n - (2.0 * PI).ln() / 2.0
+    } else {
+        let n2 = n * n;
+        (1.0 / 12.0 - (1.0 / 360.0 - (1.0 / 1260.0 - 1.0 / (1680.0 * n2)) / n2) / n2) / n
+    }
+}
+
+/// `x ln(x / m) + m - x`, which is small when `x` is close to `m`, without taking the difference
+/// of two large numbers
+fn deviance(x: f64, m: f64) -> f64 {
+    x * ((x - m) / m).ln_1p() - (x - m)
+}
+
+/// Add up the terms of a distribution's tail, starting with `term` at `i` and moving away from its
+/// peak, where `next(i)` gives the next value and the ratio of its term to the term at `i`, or
+/// `None` at the end of the distribution.  This stops once the terms are too small to change the
+/// sum.
+fn tail(mut term: f64, mut i: u64, next: impl Fn(u64) -> Option<(u64, f64)>) -> f64 {
+    let mut sum = 0.0;
+    loop {
+        sum += term;
+        match next(i) {
+            Some((j, ratio)) if term > sum * f64::EPSILON => {
+                term *= ratio;
+                i = j;
+            }
+            _ => return sum,
+        }
+    }
+}
+
+/// Find the smallest `k` such that `cdf(k) >= q` for a discrete distribution with the given mean
+/// and standard deviation, where `pdf` is the probability of each value and `max` is the largest
+/// value it can take.
+///
+/// This starts from the value the normal distribution with the same mean and standard deviation
+/// gives, and moves one value at a time from there.
+fn discrete_inv(
+    q: f64,
+    (mean, stddev): (f64, f64),
+    max: u64,
+    cdf: impl Fn(u64) -> f64,
+    pdf: impl Fn(u64) -> f64,
+) -> anyhow::Result<u64> {
+    let z = normal_inv(q, 0.0, 1.0)?;
+    let mut k = (mean + stddev * z).round().clamp(0.0, max as f64) as u64;
+    let mut sum = cdf(k);
+    for _ in 0..MAX_STEPS {
+        if sum >= q {
+            if k == 0 || sum - pdf(k) < q {
+                return Ok(k);
+            }
+            sum -= pdf(k);
+            k -= 1;
+        } else {
+            if k == max {
+                return Ok(k);
+            }
+            k += 1;
+            let pdf = pdf(k);
+            // Once we're past the peak and the pdf underflows, the cdf can no longer grow
+            if pdf == 0.0 && k as f64 > mean {
+                return Ok(k);
+            }
+            sum += pdf;
+        }
+    }
+    bail!("Failed to find the inverse after {} steps", MAX_STEPS)
+}
+
+pub fn binomial_pdf(k: u64, n: u64, p: f64) -> f64 {
+    if k > n {
+        return 0.0;
+    }
+    if p == 0.0 || p == 1.0 {
+        let certain = if p == 0.0 { 0 } else { n };
+        return if k == certain { 1.0 } else { 0.0 };
+    }
+    if n <= EXACT_BINOMIAL {
+        let r = k.min(n - k);
+        let choose = (1..=r).fold(1.0, |c, i| c * (n - r + i) as f64 / i as f64);
+        return choose * p.powf(k as f64) * (1.0 - p).powf((n - k) as f64);
+    }
+    let (k, n) = (k as f64, n as f64);
+    if k == 0.0 {
+        return (n * (-p).ln_1p()).exp();
+    }
+    if k == n {
+        return (n * p.ln()).exp();
+    }
+    // Catherine Loader's saddle point form, which is accurate even when `n` is huge
+    let ln_pdf = stirling_error(n)
+        - stirling_error(k)
+        - stirling_error(n - k)
+        - deviance(k, n * p)
+        - deviance(n - k, n * (1.0 - p));
+    ln_pdf.exp() * (n / (2.0 * PI * k * (n - k))).sqrt()
+}
+
+pub fn binomial_cdf(k: u64, n: u64, p: f64) -> f64 {
+    if k >= n || p == 0.0 {
+        return 1.0;
+    }
+    if p == 1.0 {
+        return 0.0;
+    }
+    let odds = p / (1.0 - p);
+    let mode = ((n as f64 + 1.0) * p).floor() as u64;
+    if k <= mode {
+        tail(binomial_pdf(k, n, p), k, |i| {
+            let ratio = i as f64 / (n - i + 1) as f64 / odds;
+            i.checked_sub(1).map(|j| (j, ratio))
+        })
+        .min(1.0)
+    } else {
+        let upper = tail(binomial_pdf(k + 1, n, p), k + 1, |i| {
+            let ratio = (n - i) as f64 / (i + 1) as f64 * odds;
+            (i < n).then_some((i + 1, ratio))
+        });
+        (1.0 - upper).max(0.0)
+    }
+}
+
+/// Find the smallest `k` such that `binomial_cdf(k, n, p) >= q`
+pub fn binomial_inv(q: f64, n: u64, p: f64) -> anyhow::Result<u64> {
+    ensure!(
+        (0.0..=1.0).contains(&q),
+        "Probability must be in 0..=1: {}",
+        q
+    );
+    let mean = n as f64 * p;
+    discrete_inv(
+        q,
+        (mean, (mean * (1.0 - p)).sqrt()),
+        n,
+        |k| binomial_cdf(k, n, p),
+        |k| binomial_pdf(k, n, p),
+    )
+}
+
+pub fn poisson_pdf(k: u64, lambda: f64) -> f64 {
+    if lambda == 0.0 {
+        return if k == 0 { 1.0 } else { 0.0 };
+    }
+    if k == 0 {
+        return (-lambda).exp();
+    }
+    let k = k as f64;
+    (-stirling_error(k) - deviance(k, lambda)).exp() / (2.0 * PI * k).sqrt()
+}
+
+pub fn poisson_cdf(k: u64, lambda: f64) -> f64 {
+    if lambda == 0.0 {
+        return 1.0;
+    }
+    if k as f64 <= lambda.floor() {
+        tail(poisson_pdf(k, lambda), k, |i| {
+            i.checked_sub(1).map(|j| (j, i as f64 / lambda))
+        })
+        .min(1.0)
+    } else {
+        let upper = tail(poisson_pdf(k + 1, lambda), k + 1, |i| {
+            Some((i + 1, lambda / (i + 1) as f64))
+        });
+        (1.0 - upper).max(0.0)
+    }
+}
+
+/// Find the smallest `k` such that `poisson_cdf(k, lambda) >= q`
+pub fn poisson_inv(q: f64, lambda: f64) -> anyhow::Result<u64> {
+    ensure!(
+        (0.0..1.0).contains(&q),
+        "Probability must be in 0..1: {}",
+        q
+    );
+    discrete_inv(
+        q,
+        (lambda, lambda.sqrt()),
+        u64::MAX,
+        |k| poisson_cdf(k, lambda),
+        |k| poisson_pdf(k, lambda),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * b.abs()
+    }
+
+    #[test]
+    fn normal() {
+        assert!(close(normal_cdf(0.0, 0.0, 1.0), 0.5));
+        assert!(close(normal_cdf(1.96, 0.0, 1.0), 0.9750021048517795));
+        assert!(close(normal_cdf(-5.0, 0.0, 1.0), 2.866515718791939e-7));
+        assert!(close(
+            normal_inv(0.975, 0.0, 1.0).unwrap(),
+            1.959963984540054
+        ));
+        assert!(close(
+            normal_inv(1e-10, 0.0, 1.0).unwrap(),
+            -6.361340902404056
+        ));
+        assert!(close(normal_pdf(0.0, 0.0, 1.0), 0.3989422804014327));
+    }
+
+    #[test]
+    fn discrete() {
+        assert!(close(binomial_pdf(3, 10, 0.5), 0.1171875));
+        assert!(close(binomial_pdf(7, 10, 0.2), 0.000786432));
+        assert!(close(binomial_pdf(500, 1000, 0.5), 0.025225018178360804));
+        assert!(close(binomial_cdf(10, 10, 0.3), 1.0));
+        assert_eq!(binomial_inv(0.5, 10, 0.5).unwrap(), 5);
+        assert!(close(poisson_pdf(2, 3.0), 0.22404180765538775));
+        assert_eq!(poisson_inv(0.5, 3.0).unwrap(), 3);
+        assert!(close(poisson_cdf(3, 3.0), 0.6472318887822313));
+        assert!(close(binomial_cdf(3, 10, 0.5), 0.171875));
+        assert_eq!(binomial_inv(0.0, 10, 0.5).unwrap(), 0);
+        assert_eq!(binomial_inv(1.0, 10, 0.5).unwrap(), 10);
+        assert_eq!(poisson_inv(0.0, 3.0).unwrap(), 0);
+    }
+
+    #[test]
+    fn discrete_large() {
+        // these take a few terms around the peak, instead of every term from 0
+        assert!(close(binomial_pdf(20000, 40000, 0.5), 0.003989397870199723));
+        assert!(close(binomial_cdf(20000, 40000, 0.5), 0.5019946989350999));
+        assert_eq!(binomial_inv(0.5, 100000, 0.5).unwrap(), 50000);
+        assert_eq!(binomial_inv(0.975, 100000, 0.5).unwrap(), 50310);
+        assert_eq!(poisson_inv(0.5, 1e12).unwrap(), 1000000000000);
+        assert_eq!(poisson_inv(0.975, 1e6).unwrap(), 1001960);
+        assert!(close(poisson_cdf(1000000, 1e6), 0.5002659614862837));
+    }
+}
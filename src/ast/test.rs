@@ -1,5 +1,58 @@
 use super::{lexer::*, *};
 
+/// Evaluate each statement of `$str` in turn, and check the last one gives `$expected`. With
+/// `shows` its text is checked instead, and with `near` it only has to be within 1e-8.
+macro_rules! test_eval {
+    (@last $eval: expr, $str: expr) => {{
+        let mut last = None;
+        for statement in AstStatement::parse_statements($str, None).unwrap() {
+            let value = $eval.eval(&statement);
+            last = Some(value.unwrap_or_else(|e| panic!("evaluating {}: {:?}", $str, e)));
+        }
+        last.expect("a statement")
+    }};
+    ($eval: expr, $str: expr, shows $expected: expr) => {
+        let out = test_eval!(@last $eval, $str).to_string();
+        assert_eq!(out, $expected, "evaluating {}", $str);
+    };
+    ($eval: expr, $str: expr, near $expected: expr) => {
+        let out = test_eval!(@last $eval, $str).as_number().unwrap();
+        assert!(
+            (out - $expected).abs() < 1e-8,
+            "evaluating {}: {} != {}",
+            $str,
+            out,
+            $expected
+        );
+    };
+    ($eval: expr, $str: expr, $expected: expr) => {
+        assert_eq!(test_eval!(@last $eval, $str), $expected, "evaluating {}", $str);
+    };
+}
+
+/// Check that each of the `$str`s fails, either to parse, or when one of its statements is
+/// evaluated
+macro_rules! assert_errors {
+    (parse [$($str: expr),* $(,)?]) => {
+        for invalid in [$($str),*] {
+            assert!(
+                AstStatement::parse_statements(invalid, None).is_err(),
+                "parsing {}",
+                invalid
+            );
+        }
+    };
+    ($eval: expr, [$($str: expr),* $(,)?]) => {
+        for invalid in [$($str),*] {
+            let errors = AstStatement::parse_statements(invalid, None)
+                .unwrap()
+                .iter()
+                .any(|s| $eval.eval(s).is_err());
+            assert!(errors, "evaluating {}", invalid);
+        }
+    };
+}
+
 #[test]
 fn infix_to_postfix() {
     let expr: Vec<_> = vec![
@@ -37,14 +90,17 @@ fn eval_infix() {
     let a = 2.0;
     let b = 3.0;
     let c = 4.0;
-    vars.insert("a".into(), a);
-    vars.insert("b".into(), b);
-    vars.insert("c".into(), c);
+    vars.insert("a".into(), a.into());
+    vars.insert("b".into(), b.into());
+    vars.insert("c".into(), c.into());
 
     let eval = AstEvaluator {
         variable_map: vars,
         known_functions: Default::default(),
         const_map: Default::default(),
+        user_functions: Default::default(),
+        scopes: Default::default(),
+        trace: Default::default(),
     };
 
     assert_eq!(
@@ -97,9 +153,542 @@ pub fn test_tokeniser() {
         },
         Token {
             kind: Eof,
-            span: span!(19..=20),
+            span: span!(19..=19),
         },
     ];
 
     assert_eq!(lex.collect::<Vec<_>>(), expected);
 }
+
+#[test]
+fn lists() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "[1, 2, 3] * 2", Value::from(vec![2.0, 4.0, 6.0]));
+    test_eval!(
+        eval,
+        "[1, 2, 3] + [4, 5, 6]",
+        Value::from(vec![5.0, 7.0, 9.0])
+    );
+    test_eval!(eval, "0..4", Value::from(vec![0.0, 1.0, 2.0, 3.0]));
+    test_eval!(eval, "[10, 20, 30][1]", 20.0);
+    test_eval!(eval, "[10, 20, 30][-1]", 30.0);
+    test_eval!(eval, "sum(1..5)", 10.0);
+    test_eval!(eval, "prod([1, 2, 3, 4])", 24.0);
+    test_eval!(eval, "min(3, [1, 2])", 1.0);
+    test_eval!(eval, "max([3, 1, 2])", 3.0);
+    test_eval!(eval, "mean(1, 2, 3, 4)", 2.5);
+    test_eval!(eval, "len([[1, 2], [3, 4]])", 2.0);
+    test_eval!(eval, "sort([3, 1, 2])", Value::from(vec![1.0, 2.0, 3.0]));
+
+    // an empty sum is 0, not -0
+    test_eval!(eval, "sum([])", shows "0");
+
+    test_eval!(eval, "3..1", Value::from(Vec::<f64>::new()));
+    test_eval!(eval, "-2..0", Value::from(vec![-2.0, -1.0]));
+
+    assert_errors!(
+        eval,
+        [
+            "[1, 2] + [1, 2, 3]",
+            "0.5..3",
+            // 1 is less than the gap between floats this large, so counting up never ends
+            "1e17..1e17 + 64",
+            "0..1e17",
+        ]
+    );
+}
+
+#[test]
+fn matrices() {
+    use super::matrix::Matrix;
+
+    let eval = AstEvaluator::new();
+    let m = |rows, cols, data: &[f64]| Value::Matrix(Matrix::new(rows, cols, data.to_vec()));
+
+    test_eval!(eval, "[[1, 2], [3, 4]]", m(2, 2, &[1.0, 2.0, 3.0, 4.0]));
+    test_eval!(
+        eval,
+        "[[1, 2], [3, 4]] * [[5, 6], [7, 8]]",
+        m(2, 2, &[19.0, 22.0, 43.0, 50.0])
+    );
+    test_eval!(
+        eval,
+        "[[1, 2], [3, 4]] * [1, 1]",
+        Value::from(vec![3.0, 7.0])
+    );
+    test_eval!(
+        eval,
+        "[[1, 2], [3, 4]] ** 2",
+        m(2, 2, &[7.0, 10.0, 15.0, 22.0])
+    );
+    test_eval!(eval, "[[1, 2], [3, 4]][1][0]", 3.0);
+    test_eval!(
+        eval,
+        "transpose([[1, 2, 3], [4, 5, 6]])",
+        m(3, 2, &[1.0, 4.0, 2.0, 5.0, 3.0, 6.0])
+    );
+    test_eval!(eval, "det([[1, 2], [3, 4]])", -2.0);
+    test_eval!(eval, "det([[2, 0, 0], [0, 3, 0], [0, 0, 4]])", 24.0);
+    test_eval!(
+        eval,
+        "inv([[2, 0], [0, 4]])",
+        m(2, 2, &[0.5, 0.0, 0.0, 0.25])
+    );
+    test_eval!(eval, "identity(2)", m(2, 2, &[1.0, 0.0, 0.0, 1.0]));
+    test_eval!(eval, "dot([1, 2, 3], [4, 5, 6])", 32.0);
+    test_eval!(
+        eval,
+        "cross([1, 0, 0], [0, 1, 0])",
+        Value::from(vec![0.0, 0.0, 1.0])
+    );
+    test_eval!(
+        eval,
+        "linsolve([[2, 0], [0, 4]], [2, 2])",
+        Value::from(vec![1.0, 0.5])
+    );
+
+    // singular, or too large to allocate
+    assert_errors!(
+        eval,
+        [
+            "inv([[1, 2], [2, 4]])",
+            "identity(100000)",
+            "identity(3037000500)",
+        ]
+    );
+}
+
+#[test]
+fn statistics() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "median(3, 1, 2, 4)", 2.5);
+    test_eval!(eval, "mode([1, 2, 2, 3, 3])", 2.0);
+    test_eval!(eval, "pvariance([2, 4, 4, 4, 5, 5, 7, 9])", 4.0);
+    test_eval!(eval, "stddev([1, 3])", f64::sqrt(2.0));
+    test_eval!(eval, "percentile([1, 2, 3, 4], 25)", 1.75);
+    test_eval!(eval, "range(1..10)", 8.0);
+    test_eval!(eval, "harmean(1, 4, 4)", 2.0);
+    test_eval!(
+        eval,
+        "zscore([2, 9], [2, 4, 4, 4, 5, 5, 7, 9])",
+        Value::from(vec![-1.5, 2.0])
+    );
+    test_eval!(eval, "binomcdf(5, 10, 0.5)", 0.623046875);
+
+    assert_errors!(
+        eval,
+        [
+            "variance(1)",
+            "normpdf(0, 0, 0)",
+            "normcdf(0, 0, -1)",
+            "norminv(0.5, 0, 0)",
+            "zscore(1, [2, 2])",
+        ]
+    );
+}
+
+#[test]
+fn number_theory() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "5!", 120.0);
+    test_eval!(eval, "(2 + 3)! + 1", 121.0);
+    test_eval!(
+        eval,
+        "factorial(30) + 1",
+        Value::Int(265252859812191058636308480000001)
+    );
+    test_eval!(
+        eval,
+        "nCr(100, 50)",
+        Value::Int(100891344545564193334812497256)
+    );
+    test_eval!(eval, "nPr(10, 3)", 720.0);
+    test_eval!(eval, "gcd(-4, 6)", 2.0);
+    test_eval!(eval, "gcd(-7)", 7.0);
+    test_eval!(eval, "lcm(4, 6, 10)", 60.0);
+    test_eval!(eval, "isprime(1000000007)", 1.0);
+    test_eval!(eval, "nextprime(100)", 101.0);
+    test_eval!(
+        eval,
+        "factor(600851475143)",
+        Value::from(vec![71.0, 839.0, 1471.0, 6857.0])
+    );
+    test_eval!(eval, "totient(36)", 12.0);
+    test_eval!(eval, "modpow(2, 1000, 1000000007)", 688423210.0);
+    test_eval!(eval, "modinv(3, 11)", 4.0);
+    test_eval!(
+        eval,
+        "fib(150)",
+        Value::Int(9969216677189303386214405760200)
+    );
+    test_eval!(eval, "div(-7, 2)", -4.0);
+    test_eval!(eval, "mod(-7, 2)", 1.0);
+    test_eval!(
+        eval,
+        "2 ** 100 + 1",
+        Value::Int(1267650600228229401496703205377)
+    );
+    // too large for a float, which gives up as soon as it overflows
+    test_eval!(eval, "factorial(10000000000)", f64::INFINITY);
+    test_eval!(eval, "nCr(1000000000000, 500000000000)", f64::INFINITY);
+    test_eval!(eval, "nPr(1000000000000, 1000000000000)", f64::INFINITY);
+
+    // i128::MIN has no positive counterpart
+    assert_errors!(
+        eval,
+        [
+            "gcd(1.5, 3)",
+            "gcd(-2 ** 126 * 2)",
+            "lcm(-2 ** 126 * 2, 1)",
+            "div(-2 ** 126 * 2, -1)",
+            "mod(-2 ** 126 * 2, -1)",
+        ]
+    );
+}
+
+#[test]
+fn bit_manipulation() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "popcount(-1, 32)", 32.0);
+    test_eval!(eval, "clz(1, 32)", 31.0);
+    test_eval!(eval, "ctz(0x80)", 7.0);
+    test_eval!(eval, "clz(1)", 63.0);
+    test_eval!(eval, "bitrev(1, 8)", 128.0);
+    test_eval!(eval, "bswap32(0x12345678)", 0x78563412 as f64);
+    test_eval!(
+        eval,
+        "bswap64(0xffffffffffffff00)",
+        Value::int(0x00ffffffffffffff)
+    );
+    test_eval!(eval, "setbit(0, 63)", Value::int(1 << 63));
+    test_eval!(eval, "clearbit(0xff, 0)", 254.0);
+    test_eval!(eval, "togglebit(0b1010, 1)", 8.0);
+    test_eval!(eval, "bits(0xabcd, 11, 4)", 0xbc as f64);
+    test_eval!(eval, "mask(12)", 0xfff as f64);
+    test_eval!(eval, "rotl(0x81, 1, 8)", 3.0);
+    test_eval!(eval, "rotr(0x81, 1, 8)", 0xc0 as f64);
+    test_eval!(eval, "sext(0xff, 8)", -1.0);
+    test_eval!(eval, "0xffffffffffffffff & 0xff", 255.0);
+    // the widest integers
+    test_eval!(eval, "popcount(1, 127)", 1.0);
+    test_eval!(eval, "sext(1, 127)", 1.0);
+    test_eval!(eval, "clz(1, 127)", 126.0);
+    test_eval!(eval, "popcount(-1, 127)", 127.0);
+    test_eval!(eval, "clz(1, 128)", 127.0);
+    test_eval!(eval, "popcount(-1, 128)", 128.0);
+
+    assert_errors!(
+        eval,
+        [
+            "sext(0x1ff, 8)",
+            "clz(1.5, 8)",
+            "bit(1, 128)",
+            "rotl(1, 1, 0)",
+        ]
+    );
+}
+
+#[test]
+fn signed_literals() {
+    let eval = AstEvaluator::new();
+    let signed = |s: &str, width| eval.eval(&AstStatement::parse_signed(s, width).unwrap());
+
+    assert_eq!(signed("0xffffffff", 32).unwrap(), -1.0);
+    assert_eq!(signed("0x7fffffff", 32).unwrap(), 2147483647.0);
+    assert_eq!(signed("0b1000 + 1", 4).unwrap(), -7.0);
+    assert_eq!(signed("255", 8).unwrap(), 255.0);
+    assert_eq!(
+        signed("0x8000000000000000", 64).unwrap(),
+        Value::int(i64::MIN as i128)
+    );
+    assert!(AstStatement::parse_signed("0x100", 8).is_err());
+}
+
+#[test]
+fn floats() {
+    let eval = AstEvaluator::new();
+
+    test_eval!(eval, "f32bits(1)", 0x3f800000 as f64);
+    test_eval!(eval, "f64bits(1)", Value::int(0x3ff0000000000000));
+    test_eval!(eval, "fromf32bits(0x40490fdb)", std::f32::consts::PI as f64);
+    test_eval!(eval, "fromf64bits(f64bits(0.1))", 0.1);
+    test_eval!(eval, "ulp(1)", f64::EPSILON);
+    test_eval!(eval, "nextafter(1, 2) - 1", f64::EPSILON);
+    test_eval!(eval, "ulpdiff(1, nextafter(nextafter(1, 0), 0))", 2.0);
+    test_eval!(eval, "0x1.8p3", 12.0);
+    test_eval!(eval, "0x1p-2 + 0", 0.25);
+}
+
+#[test]
+fn characters() {
+    let eval = AstEvaluator::new();
+
+    test_eval!(eval, "'A' + 1", 66.0);
+    test_eval!(eval, "'a'", Value::Char('a'));
+    test_eval!(eval, "chr('a' + 1)", Value::Char('b'));
+    test_eval!(eval, "ord('\\n')", 10.0);
+    test_eval!(eval, "ord(\"é\")", 233.0);
+    test_eval!(eval, "\"abc\"", Value::from(vec![97.0, 98.0, 99.0]));
+    test_eval!(eval, "sum(\"abc\")", 294.0);
+    test_eval!(
+        eval,
+        "chr([104, 105])",
+        Value::List(vec![Value::Char('h'), Value::Char('i')])
+    );
+
+    assert_errors!(eval, ["chr(-1)", "chr(0xd800)", "ord(\"ab\")", "ord(1)"]);
+}
+
+#[test]
+fn checksums() {
+    let eval = AstEvaluator::new();
+
+    test_eval!(eval, "crc32(\"123456789\")", 0xcbf43926_u32 as f64);
+    test_eval!(
+        eval,
+        "crc16modbus([0x31, 0x32, 0x33, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39])",
+        0x4b37 as f64
+    );
+    test_eval!(
+        eval,
+        "crc8(0x31, 0x32, 0x33)",
+        checksum::CRC8.checksum(b"123") as f64
+    );
+    test_eval!(eval, "adler32(\"Wikipedia\")", 0x11e60398 as f64);
+    test_eval!(eval, "sum8(0xff, 2)", 1.0);
+    test_eval!(eval, "xor8(\"ab\")", 3.0);
+
+    assert_errors!(eval, ["crc32([256])"]);
+}
+
+#[test]
+fn calculus() {
+    let eval = AstEvaluator::new();
+
+    test_eval!(eval, "diff(x ** 3, x, 2)", near 12.0);
+    test_eval!(eval, "diff(sin(t), t, 0)", near 1.0);
+    test_eval!(eval, "integrate(x ** 2, x, 0, 3)", near 9.0);
+    test_eval!(eval, "integrate(e ** (-x ** 2), x, -inf, inf)", near std::f64::consts::PI.sqrt()
+    );
+    test_eval!(eval, "solve(x ** 2 - 2, x, 1)", near std::f64::consts::SQRT_2);
+    test_eval!(eval, "root(cos(x) - x, x, 0, 1)", near 0.7390851332151607);
+    // the bound variable shadows constants, and only exists within the expression
+    test_eval!(eval, "integrate(e, e, 0, 2)", near 2.0);
+    test_eval!(eval, "integrate(diff(y ** 2, y, x), x, 0, 1)", near 1.0);
+    // a minus before a power always negates the whole power, however it's written
+    test_eval!(eval, "-2 ** 2", near - 4.0);
+    test_eval!(eval, "- 2 ** 2", near - 4.0);
+    test_eval!(eval, "-2**2", near - 4.0);
+    test_eval!(eval, "-pi ** 2", near - std::f64::consts::PI.powi(2));
+
+    assert_errors!(eval, ["diff(x, 2, 1)", "root(x ** 2 + 1, x, -1, 1)", "x"]);
+}
+
+#[test]
+fn series() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "sum(i, 1, 100, i ** 2)", 338350.0);
+    test_eval!(eval, "prod(k, 1, 5, k)", 120.0);
+    test_eval!(eval, "sum(i, 1, 3, sum(j, 1, i, j))", 10.0);
+    test_eval!(eval, "sum(k, 0, 2, [k, 1])", Value::from(vec![3.0, 3.0]));
+    // an empty range gives the identity
+    test_eval!(eval, "sum(i, 1, 0, i)", 0.0);
+    test_eval!(eval, "prod(i, 1, 0, i)", 1.0);
+    // terms are exact integers where possible
+    test_eval!(
+        eval,
+        "prod(k, 1, 30, k) + 1",
+        Value::Int(265252859812191058636308480000001)
+    );
+    // without a loop variable, these are still the sum/product of their arguments
+    test_eval!(eval, "sum(1, 2, 3, 4)", 10.0);
+    // names which already have a value are summed as values, not used as the loop variable
+    test_eval!(eval, "sum(pi, 1, 2, 3)", std::f64::consts::PI + 6.0);
+    test_eval!(eval, "prod(e, 1, 1, 5)", std::f64::consts::E * 5.0);
+
+    assert_errors!(
+        eval,
+        ["sum(i, 1, 1e7, i)", "sum(i, 0.5, 2, i)", "sum(i, 1, 2, j)"]
+    );
+}
+
+#[test]
+fn simplify() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "simplify((x + 1) * (x + 2))", shows "x ** 2 + 3 * x + 2");
+    test_eval!(eval, "simplify(x * 2 / 4)", shows "x / 2");
+    // equivalent formulas simplify to the same thing
+    test_eval!(eval, "simplify((a + b) ** 2 - a * (a + 2 * b))", shows "b ** 2");
+    test_eval!(eval, "simplify(2 * 3)", shows "6");
+    test_eval!(eval, "deriv(x ** 3 + sin(x), x)", shows "3 * x ** 2 + cos(x)");
+    test_eval!(eval, "deriv(t ** 2, t, 3)", shows "6");
+    test_eval!(eval, "deriv(deriv(x ** 4, x), x)", shows "12 * x ** 2");
+    test_eval!(eval, "deriv(pi * r ** 2, r)", shows "2 * pi * r");
+
+    assert_errors!(
+        eval,
+        [
+            "simplify(x) + 1",
+            "sin(simplify(x))",
+            "simplify(x, y)",
+            "deriv(x, 2)",
+            "deriv(gcd(x, 4), x)",
+        ]
+    );
+}
+
+#[test]
+fn equations() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "solve(x ** 2 - 5 * x + 6 = 0, x)", shows "[2, 3]");
+    test_eval!(eval, "solve(2 * t = 7, t)", shows "[3.5]");
+    test_eval!(eval, "solve(x ** 2 + 2 * x + 5, x)", shows "[-1 - 2 * i, -1 + 2 * i]");
+    test_eval!(eval, "solve((x - 1) * (x + 2) * (x - 3), x)", shows "[-2, 1, 3]");
+    test_eval!(eval, "solve(x ** 4 = 16, x)", shows "[-2, 2, -2 * i, 2 * i]");
+    // repeated roots are found exactly, rather than spread around the root
+    test_eval!(eval, "solve((x + 1) ** 8, x)", shows "[-1, -1, -1, -1, -1, -1, -1, -1]");
+    test_eval!(eval, "solve((x - 2) ** 3 * (x + 1) ** 2, x)", shows "[-1, -1, 2, 2, 2]");
+    test_eval!(eval, "solve((x ** 2 + 1) ** 2, x)", shows "[-i, -i, i, i]");
+    // the largest degree which can be solved
+    test_eval!(eval, "len(solve(x ** 100 + x + 1, x))", shows "100");
+    test_eval!(eval, "solve([x + y = 3, x - y = 1], [x, y])", shows "[2, 1]");
+    test_eval!(eval, "solve(x ** 2 = 2, x, 1)", shows "1.414213562373095");
+    test_eval!(eval, "1 + 1 = 2", shows "1");
+    test_eval!(eval, "[1, 2] = [1, 3]", shows "0");
+
+    assert_errors!(
+        eval,
+        [
+            "solve(sin(x), x)",
+            "solve(x = x, x)",
+            "solve(x * y = 1, [x, y])",
+            "solve([x + y = 1], [x, y])",
+        ]
+    );
+
+    // polynomials which are too large to solve say so, rather than hanging
+    for large in ["solve(x ** 1000000 - 1, x)", "solve((x + 1) ** 30, x)"] {
+        let parsed: AstStatement = large.parse().unwrap();
+        let err = eval.eval(&parsed).unwrap_err().root_cause().to_string();
+        assert!(
+            err.contains("too large") || err.contains("up to degree"),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+fn explain() {
+    let eval = AstEvaluator::new();
+    macro_rules! test_trace {
+        ($str: literal, $expected: literal) => {
+            let parsed: AstStatement = $str.parse().unwrap();
+            eval.start_trace();
+            let _ = eval.eval(&parsed);
+            assert_eq!(
+                eval.take_trace().unwrap().to_string(),
+                $expected,
+                "explaining {}",
+                $str
+            );
+        };
+    }
+
+    test_trace!("1 + 2 * 3", "1 + 2 * 3 = 7\n  2 * 3 = 6\n  1 + 6 = 7\n");
+    test_trace!("-sqrt(4)", "-sqrt(4) = -2\n  sqrt(4) = 2\n  -2 = -2\n");
+    test_trace!(
+        "sum(k, 1, 3, k) * e",
+        "sum(k, 1, 3, k) * e = 16.30969097075427\n  sum(k, 1, 3, k) = 6\n  e = 2.718281828459045\n  6 * 2.718281828459045 = 16.30969097075427\n"
+    );
+    test_trace!(
+        "2 + nope",
+        "2 + nope failed: Undeclared variable or constant: 'nope'\n  nope failed: Undeclared variable or constant: 'nope'\n"
+    );
+
+    // nothing is recorded unless asked for
+    eval.eval(&"1 + 1".parse().unwrap()).unwrap();
+    assert_eq!(eval.take_trace(), None);
+}
+
+#[test]
+fn statements() {
+    let parse = |s, width| -> Vec<String> {
+        AstStatement::parse_statements(s, width)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    };
+    assert_eq!(
+        parse("1; x /* a comment */ # another", None),
+        vec!["1", "x"]
+    );
+    assert_eq!(parse("; // nothing\n;", None), Vec::<String>::new());
+    assert_eq!(parse("0xff; 0x7f", Some(8)), vec!["-1", "127"]);
+
+    assert!("1; 2".parse::<AstStatement>().is_err());
+}
+
+#[test]
+fn blocks() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "{ let a = 2; let b = a * 3; a + b }", shows "8");
+    test_eval!(eval, "{ 1 } + { let a = 1; a } * 2", shows "3");
+    test_eval!(eval, "{ let a = 2; let a = a + 1; a }", shows "3");
+    test_eval!(eval, "{ let a = 1; let b = { let a = 10; a + 1 }; a + b }", shows "12");
+    test_eval!(eval, "{ let pi = 3; pi } + pi", shows "6.141592653589793");
+    test_eval!(eval, "sum(k, 1, 3, { let s = k ** 2; s + 1 })", shows "17");
+    test_eval!(eval, "[{ let a = 1; [a, 2] }, 3]", shows "[[1, 2], 3]");
+
+    // the variables only exist inside the block
+    assert_errors!(
+        eval,
+        ["{ let a = 2; a } + a", "{ let a = 2; let b = b; a }"]
+    );
+    assert_errors!(parse [
+        "{ let a = 2; }",
+        "{ 1; 2 }",
+        "{ let 2 = a; a }",
+        "{ let a = 1; a",
+        "{}",
+        "let a = 2",
+    ]);
+
+    let parsed: AstStatement = "{ let a = 2; a*3 }+1".parse().unwrap();
+    assert_eq!(parsed.to_string(), "{ let a = 2; a * 3 } + 1");
+}
+
+#[test]
+fn user_functions() {
+    let eval = AstEvaluator::new();
+    test_eval!(eval, "let sq(x) = x ** 2; sq(3) + sq(4)", shows "25");
+    test_eval!(eval, "let df(x) = deriv(x ** 3 + sin(x), x)", shows "3 * x ** 2 + cos(x)");
+    test_eval!(eval, "df(0)", shows "1");
+    test_eval!(eval, "let f(a, b) = a * b + 1; f(2, df(0))", shows "3");
+    test_eval!(eval, "let f(a, b) = a - b; f(5, 3)", shows "2");
+    test_eval!(eval, "let g() = 4; g() * 2", shows "8");
+    test_eval!(eval, "let h(x) = { let y = x + 1; y * x }; h(3)", shows "12");
+
+    let parsed = AstStatement::parse_statements("let f(x, y) = x*y", None).unwrap();
+    assert_eq!(parsed[0].to_string(), "let f(x, y) = x * y");
+
+    assert_errors!(
+        eval,
+        [
+            "sq(1, 2)",
+            "sq()",
+            "let sin(x) = x",
+            "let r(x) = r(x) + 1",
+            "let p(x) = q(x); let q(x) = p(x); p(1)",
+        ]
+    );
+    assert_errors!(parse [
+        "let f(x, x) = x",
+        "let f(1) = 1",
+        "let f(x) x",
+        "let f(x = x",
+        "let a = 2",
+        "1 + let f(x) = x",
+    ]);
+}
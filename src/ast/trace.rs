@@ -0,0 +1,58 @@
+use std::fmt::Display;
+
+use super::value::Value;
+
+/// The most steps which are recorded, so that explaining a big calculation doesn't flood the
+/// terminal
+const MAX_STEPS: usize = 500;
+
+/// The steps taken while evaluating an expression: variable lookups, function calls, and
+/// operators, in the order they were performed.  This is shown as a tree, with the steps needed
+/// for each one indented below it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    depth: usize,
+    /// Each step and its depth in the tree
+    steps: Vec<(usize, String)>,
+    /// The number of steps which were left out, since there were too many
+    skipped: usize,
+}
+
+impl Trace {
+    /// Start a step, returning its index if it's recorded, which is given to [`Trace::exit`]
+    pub fn enter(&mut self) -> Option<usize> {
+        self.depth += 1;
+        if self.steps.len() >= MAX_STEPS {
+            self.skipped += 1;
+            return None;
+        }
+        self.steps.push((self.depth - 1, String::new()));
+        Some(self.steps.len() - 1)
+    }
+
+    /// Finish a step, describing it along with its result
+    pub fn exit<D>(&mut self, step: Option<usize>, describe: D, result: &anyhow::Result<Value>)
+    where
+        D: FnOnce() -> String,
+    {
+        self.depth -= 1;
+        if let Some(i) = step {
+            self.steps[i].1 = match result {
+                Ok(v) => format!("{} = {}", describe(), v),
+                Err(e) => format!("{} failed: {}", describe(), e.root_cause()),
+            };
+        }
+    }
+}
+
+impl Display for Trace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (depth, step) in &self.steps {
+            writeln!(f, "{:indent$}{}", "", step, indent = depth * 2)?;
+        }
+        if self.skipped > 0 {
+            writeln!(f, "... and {} more steps", self.skipped)?;
+        }
+        Ok(())
+    }
+}
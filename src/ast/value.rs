@@ -0,0 +1,252 @@
+use std::fmt::Display;
+
+use anyhow::{bail, ensure, Context};
+
+use super::{matrix::Matrix, symbolic::Expr};
+
+/// The largest integer which can be stored exactly in an f64: 2^53
+pub const MAX_EXACT_F64: i128 = 1 << f64::MANTISSA_DIGITS;
+
+/// The result of evaluating an [`AstStatement`](super::AstStatement)
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// A single number
+    Number(f64),
+    /// An integer which is too large to be stored exactly in an f64.  Use [`Value::int`] to
+    /// create integers, which only uses this when it's needed.
+    Int(i128),
+    /// A list of values: `[1, 2, 3]`
+    List(Vec<Value>),
+    /// A matrix of numbers: `[[1, 2], [3, 4]]`
+    Matrix(Matrix),
+    /// A character, which is its code point when used as a number
+    Char(char),
+    /// A symbolic expression, from `simplify(expr)`
+    Expr(Box<Expr>),
+}
+
+impl Value {
+    /// Create a list from `items`.  If every item is a list of numbers, and they all have the same
+    /// length, this will create a [`Value::Matrix`] instead.
+    pub fn list(items: Vec<Value>) -> Value {
+        let cols = match items.first() {
+            Some(Value::List(row)) if !row.is_empty() => row.len(),
+            _ => return Value::List(items),
+        };
+
+        let is_matrix = items.iter().all(|row| match row {
+            Value::List(row) => {
+                row.len() == cols
+                    && row
+                        .iter()
+                        .all(|n| matches!(n, Value::Number(_) | Value::Int(_)))
+            }
+            _ => false,
+        });
+        if !is_matrix {
+            return Value::List(items);
+        }
+
+        let mut data = Vec::with_capacity(items.len() * cols);
+        for row in &items {
+            row.flatten_into(&mut data)
+                .expect("checked that every item is a number");
+        }
+        Value::Matrix(Matrix::new(items.len(), cols, data))
+    }
+
+    /// Create an exact integer, using [`Value::Number`] if it can be stored exactly in an f64
+    pub fn int(n: i128) -> Value {
+        if n.unsigned_abs() <= MAX_EXACT_F64 as u128 {
+            Value::Number(n as f64)
+        } else {
+            Value::Int(n)
+        }
+    }
+
+    /// Get the number stored in this value, failing if it is a list
+    pub fn as_number(&self) -> anyhow::Result<f64> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            Value::Int(n) => Ok(*n as f64),
+            Value::Char(c) => Ok(*c as u32 as f64),
+            Value::List(_) => bail!("Expected a number, found list: {}", self),
+            Value::Matrix(_) => bail!("Expected a number, found matrix: {}", self),
+            Value::Expr(_) => bail!("Expected a number, found expression: {}", self),
+        }
+    }
+
+    /// Get the numbers stored in a flat list, failing if this is not a list of numbers
+    pub fn as_vector(&self) -> anyhow::Result<Vec<f64>> {
+        match self {
+            Value::List(items) => items.iter().map(Value::as_number).collect(),
+            _ => bail!("Expected a list of numbers, found {}", self),
+        }
+    }
+
+    /// Get the matrix stored in this value, failing if it is not a matrix
+    pub fn as_matrix(&self) -> anyhow::Result<&Matrix> {
+        match self {
+            Value::Matrix(m) => Ok(m),
+            _ => bail!("Expected a matrix, found {}", self),
+        }
+    }
+
+    /// Convert a matrix into a list of its rows, leaving any other value unchanged
+    pub fn into_rows(self) -> Value {
+        match self {
+            Value::Matrix(m) => Value::List((0..m.rows()).map(|r| m.row(r).into()).collect()),
+            v => v,
+        }
+    }
+
+    /// Get the number stored in this value as an exact integer, if it is one
+    pub fn to_exact_int(&self) -> Option<i128> {
+        match self {
+            Value::Int(n) => Some(*n),
+            Value::Char(c) => Some(*c as i128),
+            // i128::MAX as f64 rounds up to 2^127, so this excludes everything which would not fit
+            Value::Number(n) if n.fract() == 0.0 && n.abs() < i128::MAX as f64 => Some(*n as i128),
+            _ => None,
+        }
+    }
+
+    /// Get the number stored in this value as an integer, failing if it has a fractional part
+    pub fn as_integer(&self) -> anyhow::Result<i128> {
+        let n = self.as_number()?;
+        self.to_exact_int()
+            .with_context(|| format!("Expected an integer, found {}", n))
+    }
+
+    /// Apply `f` to this number, or to every number within this list
+    pub fn map_number<F>(&self, f: F) -> anyhow::Result<Value>
+    where
+        F: Fn(f64) -> f64 + Copy,
+    {
+        Ok(match self {
+            Value::Number(n) => Value::Number(f(*n)),
+            Value::Int(n) => Value::Number(f(*n as f64)),
+            Value::Char(c) => Value::Number(f(*c as u32 as f64)),
+            Value::List(items) => Value::List(
+                items
+                    .iter()
+                    .map(|v| v.map_number(f))
+                    .collect::<anyhow::Result<_>>()?,
+            ),
+            Value::Matrix(m) => Value::Matrix(m.map(f)),
+            Value::Expr(_) => bail!("Expected a number, found expression: {}", self),
+        })
+    }
+
+    /// Push every number within this value into `out`, flattening any nested lists
+    pub fn flatten_into(&self, out: &mut Vec<f64>) -> anyhow::Result<()> {
+        match self {
+            Value::Number(n) => out.push(*n),
+            Value::Int(n) => out.push(*n as f64),
+            Value::Char(c) => out.push(*c as u32 as f64),
+            Value::List(items) => {
+                for v in items {
+                    v.flatten_into(out)?;
+                }
+            }
+            Value::Matrix(m) => out.extend_from_slice(m.data()),
+            Value::Expr(_) => bail!("Expected a number, found expression: {}", self),
+        }
+        Ok(())
+    }
+
+    /// Convert a character into its code point, leaving any other value unchanged
+    pub fn into_code_point(self) -> Value {
+        match self {
+            Value::Char(c) => Value::Number(c as u32 as f64),
+            v => v,
+        }
+    }
+
+    /// Get the item at `index` in this list, or the row at `index` in this matrix.  Negative
+    /// indices count from the end of the list.
+    pub fn index(&self, index: i128) -> anyhow::Result<Value> {
+        if let Value::Matrix(_) = self {
+            return self.clone().into_rows().index(index);
+        }
+
+        let Value::List(items) = self else {
+            bail!("Cannot index into a number: {}", self);
+        };
+
+        let i = if index < 0 {
+            items.len() as i128 + index
+        } else {
+            index
+        };
+
+        ensure!(
+            0 <= i && (i as usize) < items.len(),
+            "Index {} out of bounds for list of length {}",
+            index,
+            items.len()
+        );
+
+        Ok(items[i as usize].clone())
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Char(c) => write!(f, "{:?}", c),
+            Value::List(items) => {
+                write!(f, "[")?;
+                for (i, v) in items.iter().enumerate() {
+                    write!(f, "{}", v)?;
+                    if i != items.len() - 1 {
+                        write!(f, ", ")?;
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Matrix(m) => write!(f, "{}", m),
+            Value::Expr(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<Vec<f64>> for Value {
+    fn from(value: Vec<f64>) -> Self {
+        Self::List(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl From<Expr> for Value {
+    fn from(value: Expr) -> Self {
+        match value {
+            Expr::Number(n) => Self::Number(n),
+            e => Self::Expr(Box::new(e)),
+        }
+    }
+}
+
+impl From<Matrix> for Value {
+    fn from(value: Matrix) -> Self {
+        Self::Matrix(value)
+    }
+}
+
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        match self {
+            Value::Number(n) => n == other,
+            Value::Int(n) => *n as f64 == *other,
+            Value::Char(c) => *c as u32 as f64 == *other,
+            _ => false,
+        }
+    }
+}
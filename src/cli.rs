@@ -1,7 +1,13 @@
+use anyhow::ensure;
 use clap::Parser;
 use std::path::PathBuf;
 
-use super::ast::lexer::NumberKind;
+use super::{
+    ast::lexer::NumberKind,
+    dump::{Dump, DumpKind},
+    format::{Output, OutputFormat},
+    register::Layout,
+};
 
 /// Just Another CLI Calculator
 ///
@@ -22,26 +28,133 @@ pub struct Cli {
     pub quiet: bool,
 
     /// Output the result in hex
-    /// (conflicts with --bin)
+    /// (conflicts with --oct and --bin)
     ///
     /// Fractions are displayed in hex with a `.`: 12.1875 (dec) => c.3 (hex)
     #[arg(short = 'x', long, conflicts_with = "bin")]
     pub hex: bool,
 
+    /// Output the result in octal
+    /// (conflicts with --hex and --bin)
+    #[arg(short, long, conflicts_with_all = ["hex", "bin"])]
+    pub oct: bool,
+
     /// Output the result in binary
-    /// (conflicts with --hex)
+    /// (conflicts with --hex and --oct)
     ///
     /// Fractions are displayed in binary with a `.`: 5.25 (dec) => 101.01 (bin)
     #[arg(short = 'b', long, conflicts_with = "hex")]
     pub bin: bool,
 
+    /// Output the result in decimal, hex, octal, and binary at once, along with its ASCII
+    /// character and IEEE-754 bits (conflicts with --hex, --oct, --bin, --bits, and --fields)
+    #[arg(short, long, conflicts_with_all = ["hex", "oct", "bin", "bits", "fields"])]
+    pub all: bool,
+
+    /// Output the sign, exponent, and mantissa of the result's IEEE-754 double bits
+    /// (conflicts with --hex, --oct, --bin, --all, --bits, and --fields)
+    #[arg(long, conflicts_with_all = ["hex", "oct", "bin", "all", "bits", "fields"])]
+    pub float: bool,
+
+    /// Output numbers as their character, and lists of bytes as a string
+    /// (conflicts with --hex, --oct, --bin, --all, --float, --bits, and --fields)
+    #[arg(
+        short,
+        long,
+        conflicts_with_all = ["hex", "oct", "bin", "all", "float", "bits", "fields"]
+    )]
+    pub char: bool,
+
+    /// Output integers as a register of this many bits, showing each bit and its index
+    /// (conflicts with --hex, --oct, and --bin)
+    ///
+    /// Negative numbers are shown using two's complement.
+    #[arg(long, value_name = "width", conflicts_with_all = ["hex", "oct", "bin", "width"])]
+    pub bits: Option<u32>,
+
+    /// The width of a word in bits, usually 8, 16, 32, 64, or 128
+    ///
+    /// Negative integers are output in hex and binary using their two's complement bit pattern,
+    /// so `-1` is `0xff` with a width of 8.
+    #[arg(short, long, value_name = "width")]
+    pub width: Option<u32>,
+
+    /// Read hex and binary numbers as signed two's complement integers (requires --width)
+    ///
+    /// With a width of 32, `0xffffffff` is `-1`.
+    #[arg(short, long, requires = "width")]
+    pub signed: bool,
+
+    /// Output integers as a register, and decode these fields from it
+    /// (conflicts with --hex, --oct, and --bin)
+    ///
+    /// Fields are given as `NAME[hi:lo]` or `NAME[bit]`, separated by spaces: `EN[0] MODE[3:1]
+    /// DIV[15:8]`.  Unless --bits is given, the register is just wide enough to fit every field.
+    #[arg(long, value_name = "layout", conflicts_with_all = ["hex", "oct", "bin"])]
+    pub fields: Option<Layout>,
+
+    /// Show each step of the evaluation before its result: variable lookups, function calls, and
+    /// operators, with the steps each one needed indented below it
+    #[arg(short, long)]
+    pub explain: bool,
+
+    /// Read input as Reverse Polish Notation, with a stack which is kept between lines
+    ///
+    /// Each word is a value, an operator, a function, or one of `dup`, `swap`, `drop`, `roll`,
+    /// and `clear`: `3 4 + 2 *` is `14`.
+    #[arg(short, long)]
+    pub rpn: bool,
+
+    /// Show the tokens, parsed tree, or postfix order of each expression before its result, to
+    /// debug how it was parsed
+    #[arg(long, value_name = "tokens|ast|postfix")]
+    pub dump: Option<DumpKind>,
+
+    /// Show --dump output as JSON
+    #[arg(long, requires = "dump")]
+    pub json: bool,
+
     /// The file from which to read maths input
     ///
     /// Each line in the file will be parsed as a separate expression, unless it ends with a `\`,
-    /// in which case it will concatenate with the following line.
+    /// in which case it will concatenate with the following line.  Several expressions on one
+    /// line are separated by `;`, and `#`, `//`, and `/* */` start comments.
     #[arg(short, long, value_name = "file")]
     pub file: Option<PathBuf>,
 
+    /// Add the result of each line in --file to the end of the line, `1 + 2  # => 3`, and save
+    /// it to the output file, or back to --file if no output is given
+    ///
+    /// Results from an earlier run are replaced, so the file can be annotated again after it's
+    /// edited.
+    #[arg(long, value_name = "output", requires = "file")]
+    pub annotate: Option<Option<PathBuf>>,
+
+    /// Show the result of each line in --file, and show them again whenever the file changes,
+    /// highlighting the results which changed
+    ///
+    /// The file is evaluated from scratch each time, so variables from before an edit are
+    /// forgotten.
+    #[arg(long, requires = "file", conflicts_with = "annotate")]
+    pub watch: bool,
+
+    /// Evaluate each ```jacc code block in --file, which is a Markdown document, and put its
+    /// results in a ```jacc-output block after it, saving it to the output file or back to --file
+    ///
+    /// Every block is evaluated in order using the same variables and settings.
+    #[arg(
+        long,
+        value_name = "output",
+        requires = "file",
+        conflicts_with_all = ["annotate", "watch"]
+    )]
+    pub markdown: Option<Option<PathBuf>>,
+
+    /// With --markdown, check that the results of each block match its ```jacc-output block,
+    /// rather than writing them, and fail if any of them don't
+    #[arg(long, requires = "markdown")]
+    pub check: bool,
+
     #[arg(conflicts_with = "file")]
     pub content: Option<String>,
 }
@@ -56,13 +169,78 @@ impl Cli {
         })
     }
 
-    pub fn output_format(&self) -> NumberKind {
-        match (self.hex, self.bin) {
-            (true, true) => unreachable!("clap handles conflict"),
-            (true, false) => NumberKind::Hex,
-            (false, true) => NumberKind::Bin,
-            (false, false) => NumberKind::Dec,
+    pub fn output_format(&self) -> OutputFormat {
+        if self.all {
+            return OutputFormat::All;
+        }
+        if self.float {
+            return OutputFormat::Float;
+        }
+        if self.char {
+            return OutputFormat::Char;
+        }
+        if self.bits.is_some() || self.fields.is_some() {
+            return OutputFormat::Fields;
+        }
+
+        match (self.hex, self.oct, self.bin) {
+            (true, false, false) => OutputFormat::Radix(NumberKind::Hex),
+            (false, true, false) => OutputFormat::Radix(NumberKind::Oct),
+            (false, false, true) => OutputFormat::Radix(NumberKind::Bin),
+            (false, false, false) => OutputFormat::Radix(NumberKind::Dec),
+            _ => unreachable!("clap handles conflict"),
+        }
+    }
+
+    /// Get the initial settings for a session from the command-line
+    pub fn settings(&self) -> anyhow::Result<Settings> {
+        let width = self
+            .bits
+            .or(self.width)
+            .or_else(|| self.fields.as_ref().map(Layout::width));
+        if let Some(width) = width {
+            ensure!(
+                (1..=128).contains(&width),
+                "Width must be between 1 and 128 bits, found {}",
+                width
+            );
         }
+
+        Ok(Settings {
+            output: Output {
+                format: self.output_format(),
+                width,
+                layout: self.fields.clone(),
+            },
+            signed: self.signed,
+            explain: self.explain,
+            rpn: self.rpn,
+            dump: self.dump.map(|kind| Dump {
+                kind,
+                json: self.json,
+            }),
+        })
+    }
+}
+
+/// The settings for a session, which can be changed using a [`Command`](crate::command::Command)
+#[derive(Clone, Debug, PartialEq)]
+pub struct Settings {
+    pub output: Output,
+    /// Read hex and binary numbers as signed integers of `output.width` bits
+    pub signed: bool,
+    /// Show each step taken to evaluate an expression
+    pub explain: bool,
+    /// Read input as Reverse Polish Notation
+    pub rpn: bool,
+    /// Show how each expression was parsed
+    pub dump: Option<Dump>,
+}
+
+impl Settings {
+    /// The width to use for reading signed hex and binary numbers, if enabled
+    pub fn signed_width(&self) -> Option<u32> {
+        self.output.width.filter(|_| self.signed)
     }
 }
 
@@ -0,0 +1,187 @@
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context};
+
+use crate::{
+    ast::lexer::NumberKind, cli::Settings, dump::Dump, format::OutputFormat, register::Layout,
+};
+
+/// A command entered on its own line, starting with a `:`, which changes the settings of the
+/// current session
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    /// `:format dec|hex|oct|bin|fields|all|float|char`
+    Format(OutputFormat),
+    /// `:bits 32` -- show results as a register of 32 bits
+    Bits(u32),
+    /// `:fields EN[0] MODE[3:1]` -- show results as a register and decode the fields, or stop
+    /// decoding fields if none are given
+    Fields(Option<Layout>),
+    /// `:width 32` -- set the width of a word, or `:width off` to remove it
+    Width(Option<u32>),
+    /// `:signed on|off` -- read hex and binary numbers as signed integers
+    Signed(bool),
+    /// `:explain on|off` -- show each step taken to evaluate an expression
+    Explain(bool),
+    /// `:rpn on|off` -- read input as Reverse Polish Notation
+    Rpn(bool),
+    /// `:dump tokens|ast|postfix [json]` -- show how each expression was parsed, or `:dump off`
+    Dump(Option<Dump>),
+}
+
+impl Command {
+    /// Apply this command to the settings
+    pub fn run(self, settings: &mut Settings) -> anyhow::Result<()> {
+        let output = &mut settings.output;
+        match self {
+            Command::Format(format) => output.format = format,
+            Command::Bits(width) => {
+                output.format = OutputFormat::Fields;
+                output.width = Some(width);
+            }
+            Command::Fields(layout) => {
+                if let Some(ref layout) = layout {
+                    output.format = OutputFormat::Fields;
+                    output.width = Some(output.width.unwrap_or(0).max(layout.width()));
+                }
+                output.layout = layout;
+            }
+            Command::Width(width) => {
+                ensure!(
+                    width.is_some() || !settings.signed,
+                    "Signed numbers need a width, use `:signed off` first"
+                );
+                output.width = width;
+            }
+            Command::Signed(signed) => {
+                ensure!(
+                    !signed || output.width.is_some(),
+                    "Signed numbers need a width, use `:width N` first"
+                );
+                settings.signed = signed;
+            }
+            Command::Explain(explain) => settings.explain = explain,
+            Command::Rpn(rpn) => settings.rpn = rpn,
+            Command::Dump(dump) => settings.dump = dump,
+        }
+        Ok(())
+    }
+}
+
+/// Parse a width in bits, which must be in `1..=128`
+fn parse_width(s: &str) -> anyhow::Result<u32> {
+    let width = s
+        .parse()
+        .with_context(|| format!("Invalid number of bits: '{}'", s))?;
+    ensure!(
+        (1..=128).contains(&width),
+        "Width must be between 1 and 128 bits, found {}",
+        width
+    );
+    Ok(width)
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s
+            .trim()
+            .strip_prefix(':')
+            .context("Commands must start with ':'")?;
+        let (name, arg) = s.split_once(' ').unwrap_or((s, ""));
+        let arg = arg.trim();
+
+        Ok(match name {
+            "format" => Command::Format(match arg {
+                "dec" => OutputFormat::Radix(NumberKind::Dec),
+                "hex" => OutputFormat::Radix(NumberKind::Hex),
+                "oct" => OutputFormat::Radix(NumberKind::Oct),
+                "bin" => OutputFormat::Radix(NumberKind::Bin),
+                "fields" => OutputFormat::Fields,
+                "all" => OutputFormat::All,
+                "float" => OutputFormat::Float,
+                "char" => OutputFormat::Char,
+                _ => bail!(
+                    "Unknown format: '{}', expected one of: dec, hex, oct, bin, fields, all, float, char",
+                    arg
+                ),
+            }),
+            "bits" => Command::Bits(parse_width(arg)?),
+            "fields" if arg.is_empty() => Command::Fields(None),
+            "fields" => Command::Fields(Some(arg.parse()?)),
+            "width" if arg == "off" => Command::Width(None),
+            "width" => Command::Width(Some(parse_width(arg)?)),
+            "signed" => Command::Signed(match arg {
+                "on" => true,
+                "off" => false,
+                _ => bail!("Expected `:signed on` or `:signed off`"),
+            }),
+            "explain" => Command::Explain(match arg {
+                "on" => true,
+                "off" => false,
+                _ => bail!("Expected `:explain on` or `:explain off`"),
+            }),
+            "rpn" => Command::Rpn(match arg {
+                "on" => true,
+                "off" => false,
+                _ => bail!("Expected `:rpn on` or `:rpn off`"),
+            }),
+            "dump" if arg == "off" => Command::Dump(None),
+            "dump" => {
+                let (kind, json) = match arg.split_once(' ') {
+                    Some((kind, "json")) => (kind, true),
+                    Some(_) => bail!("Expected `:dump KIND` or `:dump KIND json`"),
+                    None => (arg, false),
+                };
+                Command::Dump(Some(Dump {
+                    kind: kind.parse()?,
+                    json,
+                }))
+            }
+            _ => bail!("Unknown command: ':{}'", name),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::dump::DumpKind;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            ":format hex".parse::<Command>().unwrap(),
+            Command::Format(OutputFormat::Radix(NumberKind::Hex))
+        );
+        assert_eq!(":bits 16".parse::<Command>().unwrap(), Command::Bits(16));
+        assert_eq!(":fields".parse::<Command>().unwrap(), Command::Fields(None));
+        assert_eq!(
+            ":width off".parse::<Command>().unwrap(),
+            Command::Width(None)
+        );
+        assert_eq!(
+            ":signed on".parse::<Command>().unwrap(),
+            Command::Signed(true)
+        );
+        assert_eq!(
+            ":explain off".parse::<Command>().unwrap(),
+            Command::Explain(false)
+        );
+        assert!(":explain".parse::<Command>().is_err());
+        assert_eq!(
+            ":dump ast json".parse::<Command>().unwrap(),
+            Command::Dump(Some(Dump {
+                kind: DumpKind::Ast,
+                json: true
+            }))
+        );
+        assert_eq!(":rpn on".parse::<Command>().unwrap(), Command::Rpn(true));
+        assert_eq!(":dump off".parse::<Command>().unwrap(), Command::Dump(None));
+        assert!(":dump ast xml".parse::<Command>().is_err());
+        assert!(":bits 0".parse::<Command>().is_err());
+        assert!(":format base64".parse::<Command>().is_err());
+        assert!(":nope".parse::<Command>().is_err());
+    }
+}
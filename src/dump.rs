@@ -0,0 +1,394 @@
+use std::{fmt::Write, str::FromStr};
+
+use anyhow::bail;
+
+use crate::ast::{
+    lexer::{Lexer, Token, TokenKind},
+    op::Operator,
+    AstEvaluator, AstStatement,
+};
+
+/// A stage of parsing which can be shown instead of just the result, for debugging the parser
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DumpKind {
+    /// The tokens produced by the lexer, with their spans
+    Tokens,
+    /// The parsed tree of statements
+    Ast,
+    /// The expression in postfix order, as it is evaluated
+    Postfix,
+}
+
+impl FromStr for DumpKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "tokens" => DumpKind::Tokens,
+            "ast" => DumpKind::Ast,
+            "postfix" => DumpKind::Postfix,
+            _ => bail!(
+                "Unknown dump: '{}', expected one of: tokens, ast, postfix",
+                s
+            ),
+        })
+    }
+}
+
+/// What to show about each line before it is evaluated
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Dump {
+    pub kind: DumpKind,
+    /// Show it as JSON rather than as text
+    pub json: bool,
+}
+
+impl Dump {
+    /// Show the tokens of `line`, or the tree or postfix order of each statement in it.
+    /// `signed_width` is used to parse the line in the same way as when evaluating it.
+    pub fn render(&self, line: &str, signed_width: Option<u32>) -> anyhow::Result<String> {
+        if self.kind == DumpKind::Tokens {
+            let tokens: Vec<_> = Lexer::new(line).collect();
+            return Ok(if self.json {
+                format!("{}\n", tokens_json(line, &tokens))
+            } else {
+                tokens_text(line, &tokens)
+            });
+        }
+
+        let mut out = String::new();
+        for statement in AstStatement::parse_statements(line, signed_width)? {
+            match (self.kind, self.json) {
+                (DumpKind::Ast, false) => ast_text(&statement, 0, &mut out),
+                (DumpKind::Ast, true) => {
+                    let _ = writeln!(out, "{}", ast_json(&statement));
+                }
+                (_, json) => {
+                    let postfix = match statement {
+                        AstStatement::InfixExpression(ref expr) => {
+                            AstEvaluator::infix_to_postfix(expr)?
+                        }
+                        other => vec![other],
+                    };
+                    if json {
+                        let _ = writeln!(out, "{}", json_list(postfix.iter().map(ast_json)));
+                    } else {
+                        let items: Vec<_> = postfix.iter().map(postfix_text).collect();
+                        let _ = writeln!(out, "{}", items.join(" "));
+                    }
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// The text of `line` within a token's span
+fn source(line: &str, token: &Token) -> String {
+    line.chars()
+        .skip(token.span.start())
+        .take(token.span.end().saturating_sub(token.span.start()))
+        .collect()
+}
+
+/// Show one token per line, with its span, kind, and text:
+///
+/// ```text
+/// 0..1  Number(1.0, Dec)  "1"
+/// 2..3  Plus              "+"
+/// ```
+fn tokens_text(line: &str, tokens: &[Token]) -> String {
+    let rows: Vec<_> = tokens
+        .iter()
+        .map(|t| {
+            (
+                format!("{}..{}", t.span.start(), t.span.end()),
+                format!("{:?}", t.kind),
+                format!("{:?}", source(line, t)),
+            )
+        })
+        .collect();
+    let span_width = rows.iter().map(|r| r.0.len()).max().unwrap_or(0);
+    let kind_width = rows.iter().map(|r| r.1.len()).max().unwrap_or(0);
+
+    let mut out = String::new();
+    for (span, kind, text) in rows {
+        let _ = writeln!(
+            out,
+            "{:span_width$}  {:kind_width$}  {}",
+            span,
+            kind,
+            text,
+            span_width = span_width,
+            kind_width = kind_width
+        );
+    }
+    out
+}
+
+fn tokens_json(line: &str, tokens: &[Token]) -> String {
+    json_list(tokens.iter().map(|t| {
+        // the name of the variant, without any of its fields
+        let kind = format!("{:?}", t.kind);
+        let kind = kind.split('(').next().unwrap_or_default();
+        let value = match &t.kind {
+            TokenKind::Number(n, base) => {
+                Some(format!("{}, \"base\": {}", float_json(*n), base.radix()))
+            }
+            TokenKind::Integer(n, base) => Some(format!("{}, \"base\": {}", n, base.radix())),
+            TokenKind::Char(c) => Some(string_json(&c.to_string())),
+            TokenKind::Str(bytes) => Some(string_json(&String::from_utf8_lossy(bytes))),
+            TokenKind::Ident(name) => Some(string_json(name)),
+            TokenKind::Invalid(reason) => Some(string_json(reason)),
+            _ => None,
+        };
+
+        let mut out = format!("{{\"kind\": {}", string_json(kind));
+        if let Some(value) = value {
+            let _ = write!(out, ", \"value\": {}", value);
+        }
+        let _ = write!(
+            out,
+            ", \"span\": [{}, {}], \"text\": {}}}",
+            t.span.start(),
+            t.span.end(),
+            string_json(&source(line, t))
+        );
+        out
+    }))
+}
+
+/// The name of an operator.  Negation is `neg`, like in RPN mode, so that it can't be confused
+/// with subtraction: `a neg b -`
+fn operator(op: &Operator) -> String {
+    match op {
+        Operator::Negate => "neg".into(),
+        op => op.to_string(),
+    }
+}
+
+fn postfix_text(statement: &AstStatement) -> String {
+    match statement {
+        AstStatement::Operator(op) => operator(op),
+        s => s.to_string(),
+    }
+}
+
+/// Show the tree with each child indented below its parent:
+///
+/// ```text
+/// Infix
+///   Number 1
+///   Operator +
+///   Call sqrt
+///     Infix
+///       Variable x
+/// ```
+fn ast_text(statement: &AstStatement, depth: usize, out: &mut String) {
+    let _ = write!(out, "{:indent$}", "", indent = depth * 2);
+    let children: &[AstStatement] = match statement {
+        AstStatement::Number(n) => {
+            let _ = writeln!(out, "Number {}", n);
+            &[]
+        }
+        AstStatement::Integer(n) => {
+            let _ = writeln!(out, "Integer {}", n);
+            &[]
+        }
+        AstStatement::Char(_) => {
+            let _ = writeln!(out, "Char {}", statement);
+            &[]
+        }
+        AstStatement::Str(_) => {
+            let _ = writeln!(out, "Str {}", statement);
+            &[]
+        }
+        AstStatement::Variable(name) => {
+            let _ = writeln!(out, "Variable {}", name);
+            &[]
+        }
+        AstStatement::Operator(op) => {
+            let _ = writeln!(out, "Operator {}", operator(op));
+            &[]
+        }
+        AstStatement::InfixExpression(items) => {
+            let _ = writeln!(out, "Infix");
+            items
+        }
+        AstStatement::FunctionCall { name, params } => {
+            let _ = writeln!(out, "Call {}", name);
+            params
+        }
+        AstStatement::List(items) => {
+            let _ = writeln!(out, "List");
+            items
+        }
+        AstStatement::Index { target, index } => {
+            let _ = writeln!(out, "Index");
+            ast_text(target, depth + 1, out);
+            ast_text(index, depth + 1, out);
+            &[]
+        }
+        AstStatement::Block { bindings, result } => {
+            let _ = writeln!(out, "Block");
+            for (name, value) in bindings {
+                let _ = writeln!(out, "{:indent$}Let {}", "", name, indent = depth * 2 + 2);
+                ast_text(value, depth + 2, out);
+            }
+            ast_text(result, depth + 1, out);
+            &[]
+        }
+        AstStatement::Define { name, params, body } => {
+            let _ = writeln!(out, "Define {}({})", name, params.join(", "));
+            ast_text(body, depth + 1, out);
+            &[]
+        }
+    };
+    for child in children {
+        ast_text(child, depth + 1, out);
+    }
+}
+
+fn ast_json(statement: &AstStatement) -> String {
+    match statement {
+        AstStatement::Number(n) => {
+            format!("{{\"type\": \"Number\", \"value\": {}}}", float_json(*n))
+        }
+        AstStatement::Integer(n) => format!("{{\"type\": \"Integer\", \"value\": {}}}", n),
+        AstStatement::Char(c) => format!(
+            "{{\"type\": \"Char\", \"value\": {}}}",
+            string_json(&c.to_string())
+        ),
+        AstStatement::Str(bytes) => format!(
+            "{{\"type\": \"Str\", \"value\": {}}}",
+            string_json(&String::from_utf8_lossy(bytes))
+        ),
+        AstStatement::Variable(name) => format!(
+            "{{\"type\": \"Variable\", \"name\": {}}}",
+            string_json(name)
+        ),
+        AstStatement::Operator(op) => format!(
+            "{{\"type\": \"Operator\", \"op\": {}}}",
+            string_json(&operator(op))
+        ),
+        AstStatement::InfixExpression(items) => format!(
+            "{{\"type\": \"Infix\", \"items\": {}}}",
+            json_list(items.iter().map(ast_json))
+        ),
+        AstStatement::FunctionCall { name, params } => format!(
+            "{{\"type\": \"Call\", \"name\": {}, \"args\": {}}}",
+            string_json(name),
+            json_list(params.iter().map(ast_json))
+        ),
+        AstStatement::List(items) => format!(
+            "{{\"type\": \"List\", \"items\": {}}}",
+            json_list(items.iter().map(ast_json))
+        ),
+        AstStatement::Index { target, index } => format!(
+            "{{\"type\": \"Index\", \"target\": {}, \"index\": {}}}",
+            ast_json(target),
+            ast_json(index)
+        ),
+        AstStatement::Block { bindings, result } => format!(
+            "{{\"type\": \"Block\", \"bindings\": {}, \"result\": {}}}",
+            json_list(bindings.iter().map(|(name, value)| format!(
+                "{{\"name\": {}, \"value\": {}}}",
+                string_json(name),
+                ast_json(value)
+            ))),
+            ast_json(result)
+        ),
+        AstStatement::Define { name, params, body } => format!(
+            "{{\"type\": \"Define\", \"name\": {}, \"params\": {}, \"body\": {}}}",
+            string_json(name),
+            json_list(params.iter().map(|p| string_json(p))),
+            ast_json(body)
+        ),
+    }
+}
+
+/// A JSON array of already formatted values
+fn json_list<I>(items: I) -> String
+where
+    I: Iterator<Item = String>,
+{
+    let items: Vec<_> = items.collect();
+    format!("[{}]", items.join(", "))
+}
+
+/// JSON has no infinity or NaN, so those are written as strings
+fn float_json(n: f64) -> String {
+    if n.is_finite() {
+        n.to_string()
+    } else {
+        string_json(&n.to_string())
+    }
+}
+
+fn string_json(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dump(kind: DumpKind, json: bool, line: &str) -> String {
+        Dump { kind, json }.render(line, None).unwrap()
+    }
+
+    #[test]
+    fn text() {
+        assert_eq!(
+            dump(DumpKind::Tokens, false, "1 + x"),
+            "0..1  Number(1.0, Dec)  \"1\"\n\
+             2..3  Plus              \"+\"\n\
+             4..5  Ident(\"x\")        \"x\"\n\
+             5..5  Eof               \"\"\n"
+        );
+        assert_eq!(
+            dump(DumpKind::Ast, false, "2 * sqrt(x)"),
+            "Infix\n  Number 2\n  Operator *\n  Call sqrt\n    Infix\n      Variable x\n"
+        );
+        assert_eq!(
+            dump(DumpKind::Postfix, false, "1 + 2 * (3 - 4); 5 ** 6"),
+            "1 2 3 4 - * +\n5 6 **\n"
+        );
+        assert_eq!(dump(DumpKind::Postfix, false, "-a - b"), "a neg b -\n");
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            dump(DumpKind::Tokens, true, "0x1f \"a\""),
+            "[{\"kind\": \"Number\", \"value\": 31, \"base\": 16, \"span\": [0, 4], \"text\": \"0x1f\"}, \
+             {\"kind\": \"Str\", \"value\": \"a\", \"span\": [5, 8], \"text\": \"\\\"a\\\"\"}, \
+             {\"kind\": \"Eof\", \"span\": [8, 8], \"text\": \"\"}]\n"
+        );
+        assert_eq!(
+            dump(DumpKind::Ast, true, "xs[1]"),
+            "{\"type\": \"Infix\", \"items\": [{\"type\": \"Index\", \
+             \"target\": {\"type\": \"Variable\", \"name\": \"xs\"}, \
+             \"index\": {\"type\": \"Infix\", \"items\": [{\"type\": \"Number\", \"value\": 1}]}}]}\n"
+        );
+        assert_eq!(
+            dump(DumpKind::Postfix, true, "-x"),
+            "[{\"type\": \"Variable\", \"name\": \"x\"}, {\"type\": \"Operator\", \"op\": \"neg\"}]\n"
+        );
+    }
+}
@@ -1,22 +1,192 @@
+use crate::{
+    ast::{
+        bits, float,
+        lexer::{self, NumberKind},
+        value::Value,
+    },
+    register::{self, Layout},
+};
+
+/// The default number of bits to show when showing results as a register
+pub const DEFAULT_REGISTER_WIDTH: u32 = 32;
+
+/// The style used to show results
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A single number in the given base
+    Radix(NumberKind),
+    /// Integers are shown as a register, with each bit and its index
+    Fields,
+    /// A table of the number in every base, its ASCII character, and its IEEE-754 bits
+    All,
+    /// Numbers are shown as the sign, exponent, and mantissa of their IEEE-754 bits
+    Float,
+    /// Numbers are shown as their character, and lists of bytes as a string
+    Char,
+}
+
+/// How results should be shown
+#[derive(Clone, Debug, PartialEq)]
+pub struct Output {
+    pub format: OutputFormat,
+    /// The width of a word in bits.  When set, negative integers are shown in hex and binary using
+    /// their two's complement bit pattern, and this is the size of the register for
+    /// [`OutputFormat::Fields`].
+    pub width: Option<u32>,
+    /// The fields to decode for [`OutputFormat::Fields`]
+    pub layout: Option<Layout>,
+}
+
+impl Output {
+    /// Format a result for output
+    pub fn format(&self, v: &Value) -> anyhow::Result<String> {
+        Ok(match self.format {
+            OutputFormat::Radix(NumberKind::Dec) => v.to_string(),
+            OutputFormat::Radix(NumberKind::Hex) => self.to_string_radix::<16>(v),
+            OutputFormat::Radix(NumberKind::Oct) => self.to_string_radix::<8>(v),
+            OutputFormat::Radix(NumberKind::Bin) => self.to_string_radix::<2>(v),
+            OutputFormat::Fields => match v.to_exact_int() {
+                Some(x) => register::render(
+                    x,
+                    self.width.unwrap_or(DEFAULT_REGISTER_WIDTH),
+                    self.layout.as_ref(),
+                )?,
+                // Only integers can be shown as a register
+                None => v.to_string(),
+            },
+            OutputFormat::All => self.table(v),
+            OutputFormat::Char => to_char_string(v).unwrap_or_else(|| v.to_string()),
+            OutputFormat::Float => match v {
+                Value::Number(_) | Value::Int(_) | Value::Char(_) => float::render(v.as_number()?),
+                _ => v.to_string(),
+            },
+        })
+    }
+
+    /// Show `v` in several representations at once, with one per line:
+    ///
+    /// ```text
+    /// dec  65
+    /// hex  0x41
+    /// oct  0o101
+    /// bin  0b1000001
+    /// char 'A'
+    /// f64  0x4050400000000000
+    /// ```
+    fn table(&self, v: &Value) -> String {
+        let v = &v.clone().into_code_point();
+        let mut rows = vec![
+            ("dec", v.to_string()),
+            ("hex", self.to_string_radix::<16>(v)),
+            ("oct", self.to_string_radix::<8>(v)),
+            ("bin", self.to_string_radix::<2>(v)),
+        ];
+
+        if let Value::Number(n) = v {
+            let ascii = v
+                .to_exact_int()
+                .and_then(|x| u8::try_from(x).ok())
+                .filter(|c| c.is_ascii_graphic() || *c == b' ');
+            if let Some(c) = ascii {
+                rows.push(("char", format!("{:?}", c as char)));
+            }
+            rows.push(("f64", format!("{:#018x}", n.to_bits())));
+        }
+
+        rows.iter()
+            .map(|(name, s)| format!("{:<4} {}", name, s))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Format `v` in base `N`, showing negative integers as two's complement if there is a width
+    fn to_string_radix<const N: u32>(&self, v: &Value) -> String {
+        let Some(width) = self.width else {
+            return v.to_string_radix::<N>();
+        };
+
+        match v {
+            Value::List(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|v| self.to_string_radix::<N>(v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Matrix(_) => self.to_string_radix::<N>(&v.clone().into_rows()),
+            _ => match v.to_exact_int() {
+                Some(x) if x < 0 && x >= bits::min_signed(width) => {
+                    bits::truncate(x, width).to_string_radix::<N>()
+                }
+                _ => v.to_string_radix::<N>(),
+            },
+        }
+    }
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Radix(NumberKind::Dec),
+            width: None,
+            layout: None,
+        }
+    }
+}
+
+/// Show `v` as a character (`'A'`) or as a string of bytes (`"hi\n"`), if it is one
+fn to_char_string(v: &Value) -> Option<String> {
+    match v {
+        Value::List(items) => {
+            let bytes = items
+                .iter()
+                .map(|b| u8::try_from(b.to_exact_int()?).ok())
+                .collect::<Option<Vec<_>>>()?;
+            Some(lexer::quote_bytes(&bytes))
+        }
+        v => {
+            let c = char::from_u32(u32::try_from(v.to_exact_int()?).ok()?)?;
+            Some(format!("{:?}", c))
+        }
+    }
+}
+
 pub trait ToStringRadix {
     fn to_string_radix<const N: u32>(self) -> String;
 }
 
-impl ToStringRadix for f64 {
-    fn to_string_radix<const N: u32>(mut self) -> String {
-        let mut out = String::new();
-        let neg = self < 0.0;
-        if neg {
-            self = -self;
+impl ToStringRadix for &Value {
+    fn to_string_radix<const N: u32>(self) -> String {
+        match self {
+            Value::Number(n) => n.to_string_radix::<N>(),
+            Value::Int(n) => n.to_string_radix::<N>(),
+            Value::Char(c) => (*c as i128).to_string_radix::<N>(),
+            Value::List(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(|v| v.to_string_radix::<N>())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Matrix(_) => self.clone().into_rows().to_string_radix::<N>(),
+            Value::Expr(e) => e.to_string(),
         }
-        let mut whole = self as u32;
+    }
+}
+
+impl ToStringRadix for u128 {
+    fn to_string_radix<const N: u32>(self) -> String {
+        let mut out = String::new();
+        let mut whole = self;
         if whole == 0 {
             out.push('0');
         } else {
             while whole > 0 {
-                let part = whole % N;
-                out.insert(0, char::from_digit(part, 16).unwrap());
-                whole /= N;
+                let part = (whole % N as u128) as u32;
+                out.insert(0, char::from_digit(part, N).unwrap());
+                whole /= N as u128;
             }
         }
 
@@ -25,6 +195,10 @@ impl ToStringRadix for f64 {
                 out.insert(0, 'b');
                 out.insert(0, '0');
             }
+            8 => {
+                out.insert(0, 'o');
+                out.insert(0, '0');
+            }
             16 => {
                 out.insert(0, 'x');
                 out.insert(0, '0');
@@ -32,6 +206,28 @@ impl ToStringRadix for f64 {
             _ => {}
         }
 
+        out
+    }
+}
+
+impl ToStringRadix for i128 {
+    fn to_string_radix<const N: u32>(self) -> String {
+        let out = self.unsigned_abs().to_string_radix::<N>();
+        if self < 0 {
+            format!("-{}", out)
+        } else {
+            out
+        }
+    }
+}
+
+impl ToStringRadix for f64 {
+    fn to_string_radix<const N: u32>(mut self) -> String {
+        let neg = self < 0.0;
+        if neg {
+            self = -self;
+        }
+        let mut out = (self as i128).to_string_radix::<N>();
         if neg {
             out.insert(0, '-');
         }
@@ -51,7 +247,10 @@ impl ToStringRadix for f64 {
 
 #[cfg(test)]
 mod test {
-    use crate::format::ToStringRadix;
+    use crate::{
+        ast::{lexer::NumberKind, value::Value},
+        format::{Output, OutputFormat, ToStringRadix},
+    };
 
     macro_rules! t {
         ($dec: literal, $dest: literal, $radix: literal) => {
@@ -79,5 +278,83 @@ mod test {
         t!(5.625, "0x5.a", 16);
         t!(15.625, "0xf.a", 16);
         t!(16.625, "0x10.a", 16);
+        t!(4294967296.0, "0x100000000", 16);
+    }
+
+    #[test]
+    fn oct() {
+        t!(8.0, "0o10", 8);
+        t!(0.5, "0o0.4", 8);
+    }
+
+    #[test]
+    fn table() {
+        let out = Output {
+            format: OutputFormat::All,
+            ..Default::default()
+        };
+        assert_eq!(
+            out.format(&65.0.into()).unwrap(),
+            "dec  65\n\
+             hex  0x41\n\
+             oct  0o101\n\
+             bin  0b1000001\n\
+             char 'A'\n\
+             f64  0x4050400000000000"
+        );
+        assert_eq!(
+            out.format(&vec![1.0, 10.0].into()).unwrap(),
+            "dec  [1, 10]\n\
+             hex  [0x1, 0xa]\n\
+             oct  [0o1, 0o12]\n\
+             bin  [0b1, 0b1010]"
+        );
+    }
+
+    #[test]
+    fn chars() {
+        let out = Output {
+            format: OutputFormat::Char,
+            ..Default::default()
+        };
+        assert_eq!(out.format(&65.0.into()).unwrap(), "'A'");
+        assert_eq!(
+            out.format(&vec![104.0, 105.0, 10.0].into()).unwrap(),
+            "\"hi\\n\""
+        );
+        assert_eq!(out.format(&1.5.into()).unwrap(), "1.5");
+    }
+
+    #[test]
+    fn twos_complement() {
+        let out = Output {
+            format: OutputFormat::Radix(NumberKind::Hex),
+            width: Some(32),
+            layout: None,
+        };
+        assert_eq!(out.format(&(-1.0).into()).unwrap(), "0xffffffff");
+        assert_eq!(out.format(&(-31.0).into()).unwrap(), "0xffffffe1");
+        assert_eq!(out.format(&31.0.into()).unwrap(), "0x1f");
+        // too small to fit, so just use a sign
+        assert_eq!(out.format(&(-1e10).into()).unwrap(), "-0x2540be400");
+
+        let out = Output {
+            format: OutputFormat::Radix(NumberKind::Bin),
+            width: Some(8),
+            layout: None,
+        };
+        assert_eq!(
+            out.format(&vec![-2.0, 2.0].into()).unwrap(),
+            "[0b11111110, 0b10]"
+        );
+
+        let out = Output {
+            width: Some(128),
+            ..out
+        };
+        assert_eq!(
+            out.format(&Value::int(i128::MIN)).unwrap(),
+            format!("0b1{}", "0".repeat(127))
+        );
     }
 }
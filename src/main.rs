@@ -3,17 +3,22 @@ use std::{
     io::{stdout, BufRead, BufReader, Write},
 };
 
-use anyhow::Context;
+use anyhow::{ensure, Context};
 use clap::Parser;
-use cli::{Cli, ContentSource};
+use cli::{Cli, ContentSource, Settings};
+use command::Command;
 
 use crate::ast::*;
 
+mod annotate;
 mod ast;
 mod cli;
+mod command;
+mod dump;
 mod format;
-
-use format::ToStringRadix;
+mod markdown;
+mod register;
+mod watch;
 
 #[cfg(test)]
 mod test;
@@ -23,6 +28,8 @@ where
     R: BufRead,
 {
     let mut eval = AstEvaluator::new();
+    let mut settings = cli.settings()?;
+    let mut stack = rpn::Stack::default();
 
     let mut s = String::new();
     if !cli.quiet {
@@ -33,16 +40,19 @@ where
         let line = line.unwrap();
 
         if line.ends_with('\\') {
+            // keep the line break, so that a `# comment` ends with its line
             s.push_str(&line[..line.len() - 1]);
+            s.push('\n');
             continue;
         }
 
-        let line = if s.is_empty() {
-            &line
-        } else {
-            s.push_str(&line);
-            &s
-        };
+        s.push_str(&line);
+        if lexer::ends_in_comment(&s) {
+            // a `/* block comment */` can span lines, so keep reading until it ends
+            s.push('\n');
+            continue;
+        }
+        let line = s.as_str();
 
         if !cli.quiet {
             match cli.content_source()? {
@@ -52,31 +62,57 @@ where
             }
         }
 
-        let statement = line.parse()?;
-
-        s.clear();
-
-        let result = match eval.eval(&statement) {
-            Ok(r) => r,
-            Err(e) => {
-                println!("Error evaluating equation: {:?}", e);
+        if line.trim_start().starts_with(':') {
+            if let Err(e) = line
+                .parse::<Command>()
+                .and_then(|command| command.run(&mut settings))
+            {
+                println!("Error running command: {:?}", e);
+            }
+            s.clear();
+            if !cli.quiet {
                 print!("> ");
-                stdout().flush()?;
-                continue;
             }
-        };
+            stdout().flush()?;
+            continue;
+        }
 
-        eval.variable_map.insert("_".into(), result);
+        if settings.rpn {
+            let result = stack.eval_line(&eval, line, settings.signed_width());
+            s.clear();
+            match result {
+                Ok(()) => {
+                    if let Some(top) = stack.values.last() {
+                        eval.variable_map.insert("_".into(), top.clone());
+                    }
+                    print_stack(&stack, &settings, cli.quiet);
+                }
+                Err(e) => println!("Error evaluating RPN: {:?}", e),
+            }
+            if !cli.quiet {
+                print!("> ");
+            }
+            stdout().flush()?;
+            continue;
+        }
 
-        if !cli.quiet {
-            print!("{} = ", statement);
+        if let Some(dump) = settings.dump {
+            match dump.render(line, settings.signed_width()) {
+                Ok(out) => print!("{}", out),
+                Err(e) => println!("Error dumping expression: {:?}", e),
+            }
         }
 
-        match cli.output_format() {
-            lexer::NumberKind::Dec => println!("{}", result),
-            lexer::NumberKind::Hex => println!("{}", result.to_string_radix::<16>()),
-            lexer::NumberKind::Bin => println!("{}", result.to_string_radix::<2>()),
-        };
+        match AstStatement::parse_statements(line, settings.signed_width()) {
+            Ok(statements) => {
+                for statement in &statements {
+                    eval_statement(&mut eval, &settings, statement, cli.quiet);
+                }
+            }
+            // a bad line doesn't stop the lines after it
+            Err(e) => println!("Error parsing expression: {:?}", e),
+        }
+        s.clear();
 
         if !cli.quiet {
             print!("> ");
@@ -84,6 +120,11 @@ where
 
         stdout().flush()?;
     }
+    if lexer::ends_in_comment(&s) {
+        if let Err(e) = AstStatement::parse_statements(&s, settings.signed_width()) {
+            println!("Error parsing expression: {:?}", e);
+        }
+    }
     if !cli.quiet {
         println!("EOF");
     }
@@ -91,9 +132,110 @@ where
     Ok(())
 }
 
+/// Evaluate one statement and show its result, or the error if it failed
+fn eval_statement(
+    eval: &mut AstEvaluator,
+    settings: &Settings,
+    statement: &AstStatement,
+    quiet: bool,
+) {
+    if settings.explain {
+        eval.start_trace();
+    }
+    let result = eval.eval(statement);
+    if let Some(trace) = eval.take_trace() {
+        print!("{}", trace);
+    }
+
+    let result = match result {
+        Ok(r) => r,
+        Err(e) => {
+            println!("Error evaluating equation: {:?}", e);
+            return;
+        }
+    };
+
+    eval.variable_map.insert("_".into(), result.clone());
+
+    match settings.output.format(&result) {
+        Ok(out) => {
+            if !quiet {
+                // Multi-line output reads better starting on its own line
+                let sep = if out.contains('\n') { "\n" } else { " " };
+                print!("{} ={}", statement, sep);
+            }
+            println!("{}", out);
+        }
+        Err(e) => println!("Error formatting result: {:?}", e),
+    }
+}
+
+/// Show the stack with the top value last, numbered by how far it is from the top:
+///
+/// ```text
+/// 2: 3
+/// 1: 7
+/// ```
+///
+/// When `quiet`, only the top value is shown.
+fn print_stack(stack: &rpn::Stack, settings: &Settings, quiet: bool) {
+    let skip = if quiet {
+        stack.values.len().saturating_sub(1)
+    } else {
+        0
+    };
+    for (i, value) in stack.values.iter().enumerate().skip(skip) {
+        let out = settings
+            .output
+            .format(value)
+            .unwrap_or_else(|e| format!("Error formatting result: {:?}", e));
+        if quiet {
+            println!("{}", out);
+        } else {
+            println!("{}: {}", stack.values.len() - i, out);
+        }
+    }
+}
+
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
+    if let (true, ContentSource::File(f)) = (cli.watch, cli.content_source()?) {
+        return watch::watch(f, &cli.settings()?);
+    }
+
+    if let (Some(output), ContentSource::File(f)) = (&cli.markdown, cli.content_source()?) {
+        let input = std::fs::read_to_string(f).context("Reading Markdown file")?;
+        let doc = markdown::evaluate(&input, cli.settings()?);
+        if !cli.check {
+            std::fs::write(output.as_ref().unwrap_or(f), doc.text)
+                .context("Writing Markdown file")?;
+            return Ok(());
+        }
+
+        for mismatch in &doc.mismatches {
+            println!("{}", mismatch);
+        }
+        ensure!(
+            doc.mismatches.is_empty(),
+            "{} of {} blocks don't match their results",
+            doc.mismatches.len(),
+            doc.blocks
+        );
+        if !cli.quiet {
+            println!("All {} blocks match their results", doc.blocks);
+        }
+        return Ok(());
+    }
+
+    if let (Some(output), ContentSource::File(f)) = (&cli.annotate, cli.content_source()?) {
+        let input = std::fs::read_to_string(f).context("Reading file to annotate")?;
+        let annotated = annotate::annotate(&input, cli.settings()?);
+        std::fs::write(output.as_ref().unwrap_or(f), annotated)
+            .context("Writing annotated file")?;
+        return Ok(());
+    }
+
     match cli.content_source()? {
         ContentSource::File(f) => {
             let file = File::open(f).context("Opening file for reading")?;
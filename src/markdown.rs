@@ -0,0 +1,250 @@
+use std::fmt::Display;
+
+use crate::{annotate::Session, cli::Settings};
+
+/// The info string of the code blocks which are evaluated
+const INPUT: &str = "jacc";
+/// The info string of the code blocks which hold the results of the block before them
+const OUTPUT: &str = "jacc-output";
+
+/// A ```` ```jacc ```` block whose results don't match the ```` ```jacc-output ```` block after
+/// it, or which has results but no output block
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mismatch {
+    /// The line number of the start of the block, starting at 1
+    pub line: usize,
+    pub expected: Vec<String>,
+    pub found: Vec<String>,
+}
+
+/// Mismatches are shown as the lines which were expected and found, like a diff:
+///
+/// ```text
+/// Block at line 3 doesn't match its results:
+/// - 5
+/// + 6
+/// ```
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Block at line {} doesn't match its results:", self.line)?;
+        for line in &self.expected {
+            writeln!(f, "- {}", line)?;
+        }
+        for line in &self.found {
+            writeln!(f, "+ {}", line)?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of evaluating the code blocks in a document
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    /// The document with the results of each block in a block after it
+    pub text: String,
+    /// The number of blocks which were evaluated
+    pub blocks: usize,
+    /// The blocks which had results which were different to those in the document
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// The opening or closing line of a fenced code block: the fence (like ```` ``` ```` or `~~~`)
+/// and the info string after it
+fn fence(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_start();
+    let c = line.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+    let len = line.chars().take_while(|x| *x == c).count();
+    if len < 3 {
+        return None;
+    }
+    Some((&line[..len], line[len..].trim()))
+}
+
+/// Find the line which closes a code block opened by `open`, starting at `lines[start]`.  A block
+/// which is never closed runs until the end of the document.
+fn close(lines: &[&str], start: usize, open: &str) -> usize {
+    (start..lines.len())
+        .find(|i| {
+            matches!(fence(lines[*i]), Some((f, ""))
+                if f.len() >= open.len() && f.starts_with(&open[..1]))
+        })
+        .unwrap_or(lines.len())
+}
+
+/// Evaluate every ```` ```jacc ```` block in a Markdown document in order, in one session, so
+/// that each block can use the variables and settings from the blocks before it.
+///
+/// The results of each block go in a ```` ```jacc-output ```` block after it, with one line for
+/// each result, which replaces the one that's already there.  The results are also compared to
+/// the existing output block, if there is one.
+pub fn evaluate(input: &str, settings: Settings) -> Document {
+    let mut session = Session::new(settings);
+    let lines: Vec<_> = input.lines().collect();
+    let mut out = Vec::new();
+    let mut blocks = 0;
+    let mut mismatches = Vec::new();
+
+    let mut i = 0;
+    while i < lines.len() {
+        let Some((open, info)) = fence(lines[i]) else {
+            out.push(lines[i].to_string());
+            i += 1;
+            continue;
+        };
+
+        // copy the whole block, so that a fence inside it isn't mistaken for the end
+        let end = close(&lines, i + 1, open);
+        out.extend(
+            lines[i..(end + 1).min(lines.len())]
+                .iter()
+                .map(|l| l.to_string()),
+        );
+        if info != INPUT {
+            i = end + 1;
+            continue;
+        }
+
+        blocks += 1;
+        let found: Vec<_> = session
+            .evaluate(&lines[i + 1..end.min(lines.len())].join("\n"))
+            .into_iter()
+            .flat_map(|l| l.results)
+            .collect();
+
+        // the existing output block, which is after any blank lines
+        let mut next = end + 1;
+        while next < lines.len() && lines[next].trim().is_empty() {
+            next += 1;
+        }
+        let mut after = end + 1;
+        if let Some((out_open, OUTPUT)) = lines.get(next).and_then(|l| fence(l)) {
+            let out_end = close(&lines, next + 1, out_open);
+            let expected: Vec<_> = lines[next + 1..out_end.min(lines.len())]
+                .iter()
+                .map(|l| l.to_string())
+                .collect();
+            if expected != found {
+                mismatches.push(Mismatch {
+                    line: i + 1,
+                    expected,
+                    found: found.clone(),
+                });
+            }
+            after = out_end + 1;
+        } else if !found.is_empty() {
+            // results which are missing from the document don't match it either
+            mismatches.push(Mismatch {
+                line: i + 1,
+                expected: vec![],
+                found: found.clone(),
+            });
+        }
+
+        if !found.is_empty() {
+            out.push(String::new());
+            out.push(format!("```{}", OUTPUT));
+            out.extend(found);
+            out.push("```".into());
+        }
+        i = after;
+    }
+
+    let mut text = out.join("\n");
+    if input.ends_with('\n') {
+        text.push('\n');
+    }
+    Document {
+        text,
+        blocks,
+        mismatches,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli::Cli;
+
+    fn settings() -> Settings {
+        Cli::parse_from(["jacc"]).settings().unwrap()
+    }
+
+    #[test]
+    fn blocks() {
+        let input = "# Costs\n\
+                     \n\
+                     ```jacc\n\
+                     1 + 2\n\
+                     _ * 2; 4\n\
+                     ```\n\
+                     \n\
+                     ```jacc-output\n\
+                     3\n\
+                     5\n\
+                     4\n\
+                     ```\n\
+                     \n\
+                     ````md\n\
+                     ```jacc\n\
+                     nope\n\
+                     ```\n\
+                     ````\n\
+                     \n\
+                     ```jacc\n\
+                     _ + 1\n\
+                     ```\n";
+        let expected = "# Costs\n\
+                        \n\
+                        ```jacc\n\
+                        1 + 2\n\
+                        _ * 2; 4\n\
+                        ```\n\
+                        \n\
+                        ```jacc-output\n\
+                        3\n\
+                        6\n\
+                        4\n\
+                        ```\n\
+                        \n\
+                        ````md\n\
+                        ```jacc\n\
+                        nope\n\
+                        ```\n\
+                        ````\n\
+                        \n\
+                        ```jacc\n\
+                        _ + 1\n\
+                        ```\n\
+                        \n\
+                        ```jacc-output\n\
+                        5\n\
+                        ```\n";
+
+        let doc = evaluate(input, settings());
+        assert_eq!(doc.text, expected);
+        assert_eq!(doc.blocks, 2);
+        assert_eq!(
+            doc.mismatches,
+            vec![
+                Mismatch {
+                    line: 3,
+                    expected: vec!["3".into(), "5".into(), "4".into()],
+                    found: vec!["3".into(), "6".into(), "4".into()],
+                },
+                // a block without an output block doesn't match
+                Mismatch {
+                    line: 20,
+                    expected: vec![],
+                    found: vec!["5".into()],
+                },
+            ]
+        );
+
+        // evaluating it again changes nothing, and everything matches
+        let doc = evaluate(&doc.text, settings());
+        assert_eq!(doc.text, expected);
+        assert_eq!(doc.mismatches, vec![]);
+    }
+}
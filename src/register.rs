@@ -0,0 +1,210 @@
+use std::{fmt::Display, str::FromStr};
+
+use anyhow::{bail, ensure, Context};
+
+use crate::ast::bits;
+
+/// A single named field within a register: `MODE[3:1]`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Field {
+    pub name: String,
+    pub hi: u32,
+    pub lo: u32,
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.hi == self.lo {
+            write!(f, "{}[{}]", self.name, self.hi)
+        } else {
+            write!(f, "{}[{}:{}]", self.name, self.hi, self.lo)
+        }
+    }
+}
+
+impl FromStr for Field {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, range) = s
+            .strip_suffix(']')
+            .and_then(|s| s.split_once('['))
+            .with_context(|| format!("Expected a field like `NAME[hi:lo]`, found '{}'", s))?;
+
+        ensure!(
+            !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_'),
+            "Invalid field name: '{}'",
+            name
+        );
+
+        let parse_bit = |b: &str| {
+            b.trim()
+                .parse::<u32>()
+                .with_context(|| format!("Invalid bit index in field '{}': '{}'", s, b))
+        };
+        let (hi, lo) = match range.split_once(':') {
+            Some((hi, lo)) => (parse_bit(hi)?, parse_bit(lo)?),
+            None => {
+                let bit = parse_bit(range)?;
+                (bit, bit)
+            }
+        };
+
+        ensure!(hi >= lo, "Expected hi >= lo in field '{}'", s);
+        ensure!(hi < 128, "Field '{}' does not fit in 128 bits", s);
+
+        Ok(Self {
+            name: name.into(),
+            hi,
+            lo,
+        })
+    }
+}
+
+/// The fields within a register: `EN[0] MODE[3:1] DIV[15:8]`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Layout {
+    pub fields: Vec<Field>,
+}
+
+impl Layout {
+    /// The smallest whole number of bytes which fits every field
+    pub fn width(&self) -> u32 {
+        let bits = self.fields.iter().map(|f| f.hi + 1).max().unwrap_or(8);
+        (bits + 7) / 8 * 8
+    }
+}
+
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|f| !f.is_empty())
+            .map(str::parse)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        ensure!(!fields.is_empty(), "Expected at least one field");
+        Ok(Self { fields })
+    }
+}
+
+/// Show `x` as a register of `width` bits, grouped into nibbles and bytes with the bit index of
+/// each nibble above it, followed by the value of each field in `layout`.
+///
+/// ```text
+/// 0x1234 = 4660
+/// 15   11     7    3
+/// 0001 0010   0011 0100
+/// EN[0]     = 0x0 = 0
+/// ```
+pub fn render(x: i128, width: u32, layout: Option<&Layout>) -> anyhow::Result<String> {
+    let bits = fit(x, width)?;
+
+    let mut ruler = String::new();
+    let mut digits = String::new();
+    let nibbles = (width + 3) / 4;
+    for n in (0..nibbles).rev() {
+        if n != nibbles - 1 {
+            // an extra space between each byte
+            let sep = if (n + 1) % 2 == 0 { "   " } else { " " };
+            ruler.push_str(sep);
+            digits.push_str(sep);
+        }
+
+        let (hi, lo) = ((n * 4 + 3).min(width - 1), n * 4);
+        ruler.push_str(&format!("{:<4}", hi));
+        let nibble: String = (lo..=hi)
+            .rev()
+            .map(|b| if bits >> b & 1 == 1 { '1' } else { '0' })
+            .collect();
+        digits.push_str(&format!("{:>4}", nibble));
+    }
+
+    let mut out = format!("{:#x} = {}\n{}\n{}", bits, bits, ruler.trim_end(), digits);
+
+    if let Some(layout) = layout {
+        let name_width = layout
+            .fields
+            .iter()
+            .map(|f| f.to_string().len())
+            .max()
+            .unwrap_or(0);
+        for field in &layout.fields {
+            ensure!(
+                field.hi < width,
+                "Field {} does not fit in {} bits",
+                field,
+                width
+            );
+            let value = bits::field(bits, field.hi, field.lo);
+            out.push_str(&format!(
+                "\n{:<w$} = {:#x} = {}",
+                field.to_string(),
+                value,
+                value,
+                w = name_width
+            ));
+        }
+    }
+
+    Ok(out)
+}
+
+/// Get the bits of `x` within `width` bits, treating negative numbers as two's complement
+fn fit(x: i128, width: u32) -> anyhow::Result<u128> {
+    if x < bits::min_signed(width) || (width < 128 && x >= 1i128 << width) {
+        bail!("{} does not fit in a {} bit register", x, width);
+    }
+    Ok(bits::truncate(x, width))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn layout() {
+        let layout: Layout = "EN[0] MODE[3:1], DIV[15:8]".parse().unwrap();
+        assert_eq!(
+            layout.fields,
+            vec![
+                Field {
+                    name: "EN".into(),
+                    hi: 0,
+                    lo: 0
+                },
+                Field {
+                    name: "MODE".into(),
+                    hi: 3,
+                    lo: 1
+                },
+                Field {
+                    name: "DIV".into(),
+                    hi: 15,
+                    lo: 8
+                },
+            ]
+        );
+        assert_eq!(layout.width(), 16);
+
+        assert!("MODE[1:3]".parse::<Layout>().is_err());
+        assert!("MODE[3:1".parse::<Layout>().is_err());
+    }
+
+    #[test]
+    fn register() {
+        let layout: Layout = "EN[0] MODE[3:1] DIV[15:8]".parse().unwrap();
+        assert_eq!(
+            render(0x1235, 16, Some(&layout)).unwrap(),
+            "0x1235 = 4661\n\
+             15   11     7    3\n\
+             0001 0010   0011 0101\n\
+             EN[0]     = 0x1 = 1\n\
+             MODE[3:1] = 0x2 = 2\n\
+             DIV[15:8] = 0x12 = 18"
+        );
+        assert_eq!(render(-1, 6, None).unwrap(), "0x3f = 63\n5    3\n  11 1111");
+        assert!(render(0x100, 8, None).is_err());
+    }
+}
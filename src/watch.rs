@@ -0,0 +1,107 @@
+use std::{
+    io::{stdout, IsTerminal, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    annotate::{self, Line},
+    cli::Settings,
+};
+
+/// How often the file is checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Clear the terminal and move the cursor to the top left
+const CLEAR: &str = "\x1b[2J\x1b[H";
+const HIGHLIGHT: &str = "\x1b[1;33m";
+const RESET: &str = "\x1b[0m";
+
+/// Evaluate `path` as a worksheet, and evaluate it again from scratch whenever it changes, until
+/// the process is stopped.  The file is checked by polling, so it works with any editor,
+/// including those which replace the file when saving it.
+pub fn watch(path: &Path, settings: &Settings) -> anyhow::Result<()> {
+    let terminal = stdout().is_terminal();
+    let mut content = None;
+    let mut previous = Vec::new();
+    loop {
+        // the file may be missing for a moment while an editor replaces it
+        if let Ok(current) = std::fs::read_to_string(path) {
+            if content.as_ref() != Some(&current) {
+                let lines = annotate::evaluate(&current, settings.clone());
+                if terminal {
+                    print!("{}", CLEAR);
+                } else if content.is_some() {
+                    println!();
+                }
+                print!("{}", render(&lines, &previous, terminal));
+                stdout().flush()?;
+
+                content = Some(current);
+                previous = lines;
+            }
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Show each line with its results, highlighting the results which are different to the last
+/// time that line was evaluated.  Without colour, changed lines start with a `*`.
+fn render(lines: &[Line], previous: &[Line], color: bool) -> String {
+    let mut out = String::new();
+    for line in lines {
+        let before = previous.iter().find(|l| l.code == line.code);
+        let changed = if previous.is_empty() {
+            false
+        } else {
+            before.map_or(!line.results.is_empty(), |b| b.results != line.results)
+        };
+
+        let text = line.to_string();
+        match (changed, color) {
+            (false, _) => out.push_str(&text),
+            (true, true) => {
+                let (code, results) = text.split_at(line.code.len());
+                out.push_str(&format!("{}{}{}{}", code, HIGHLIGHT, results, RESET));
+            }
+            (true, false) => out.push_str(&format!("* {}", text)),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn line(code: &str, results: &[&str]) -> Line {
+        Line {
+            code: code.into(),
+            results: results.iter().map(|r| r.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn highlight() {
+        let before = [line("a", &["1"]), line("b", &["2"])];
+        let after = [
+            line("# new", &[]),
+            line("c", &["3"]),
+            line("a", &["1"]),
+            line("b", &["4"]),
+        ];
+
+        // nothing has changed the first time
+        assert_eq!(render(&before, &[], true), "a  # => 1\nb  # => 2\n");
+        assert_eq!(
+            render(&after, &before, false),
+            "# new\n* c  # => 3\na  # => 1\n* b  # => 4\n"
+        );
+        assert_eq!(
+            render(&after[3..], &before, true),
+            "b\x1b[1;33m  # => 4\x1b[0m\n"
+        );
+    }
+}
@@ -0,0 +1,48 @@
+use std::{fs, path::PathBuf, process::Command};
+
+/// Write `content` to a file of its own, so that tests running at the same time don't share one
+fn file(name: &str, content: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("jacc-{}-{}", std::process::id(), name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+fn jacc(args: &[&str]) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_jacc"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(out.status.success(), "{:?}", out);
+    String::from_utf8(out.stdout).unwrap()
+}
+
+const INPUT: &str = "/* header\n explains things */\n1 + 2\n\"oops\n3 * 4\n";
+
+#[test]
+fn file_with_block_comment() {
+    let path = file("file.txt", INPUT);
+    let out = jacc(&["-q", "-f", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+
+    // the line which doesn't parse is reported, and the lines after it still run
+    assert_eq!(
+        out,
+        "3\nError parsing expression: Unterminated string literal\n12\n"
+    );
+}
+
+#[test]
+fn annotate_with_block_comment() {
+    let path = file("annotate.txt", INPUT);
+    jacc(&["-f", path.to_str().unwrap(), "--annotate"]);
+    let annotated = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(
+        annotated,
+        "/* header\n explains things */\n\
+         1 + 2  # => 3\n\
+         \"oops  # => error: Unterminated string literal\n\
+         3 * 4  # => 12\n"
+    );
+}